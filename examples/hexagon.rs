@@ -0,0 +1,595 @@
+use nannou::prelude::*;
+use nannou_osc as osc;
+
+use std::convert::TryFrom;
+use toio::bridge::{BridgeConfig, Bridges};
+use toio::choreography::{self, Choreography, ChoreographyWatcher};
+use toio::cube::{CubeGroups, CubeId, CubeRegistry, Liveness, MotionReport};
+use toio::engine::{ButtonAction, Engine, TaskEvent};
+use toio::protocol::{InboundMessage, ProtocolError, PORT};
+use toio::toiotask::{ReloadMode, TaskQueue, DEFAULT_GROUP};
+
+fn main() {
+    nannou::app(model).update(update).run();
+}
+
+struct Model {
+    receiver: osc::Receiver,
+    bridges: Bridges,
+    toio: CubeRegistry,
+    auto_turn: bool,
+    go: bool,
+    aim_close: bool,
+    aim_away: bool,
+    aim_target: bool,
+    wiggle: bool,
+    start_wiggle: u64,
+    engine: Engine,
+    //the choreography file we reload when it changes
+    watcher: Option<ChoreographyWatcher>,
+    reload_mode: ReloadMode,
+    last_reload_check: u64,
+    //the --group ones, put back on top of the file's groups on every reload
+    group_args: Vec<(String, Vec<CubeId>)>,
+    //shown over the cubes until the file loads again
+    overlay: Option<String>,
+}
+
+//one --bridge host=address:port per toio-osc bridge, default is host 0 on TARGET_PORT
+fn bridge_configs() -> Vec<BridgeConfig> {
+    let mut configs = Vec::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--bridge" {
+            match args.next().map(|b| b.parse()) {
+                Some(Ok(config)) => configs.push(config),
+                Some(Err(e)) => println!("{}", e),
+                None => println!("--bridge needs a value"),
+            }
+        }
+    }
+    if configs.is_empty() {
+        configs.push(BridgeConfig::default());
+    }
+    configs
+}
+
+//one --group name=host:cube,host:cube per group, e.g. --group default=0:0,1:0 to pair
+//cubes of two bridges, on top of the default group and the ones of the file
+fn group_args() -> Vec<(String, Vec<CubeId>)> {
+    let mut groups = Vec::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg != "--group" {
+            continue;
+        }
+        let value = match args.next() {
+            Some(value) => value,
+            None => {
+                println!("--group needs a value");
+                continue;
+            }
+        };
+        let mut parts = value.splitn(2, '=');
+        let name = parts.next().unwrap_or("").trim().to_string();
+        let cubes: Result<Vec<CubeId>, String> = parts
+            .next()
+            .unwrap_or("")
+            .split(',')
+            .map(|c| c.parse())
+            .collect();
+        match cubes {
+            Ok(cubes) if !name.is_empty() => groups.push((name, cubes)),
+            Ok(_) => println!("bad group \"{}\", expected name=host:cube,...", value),
+            Err(e) => println!("{}", e),
+        }
+    }
+    groups
+}
+
+//--choreography path/to/file.ron (or .json, .toml), the built-in hexagon otherwise
+//--keep-step to keep going from the same step when the file changes
+fn choreography_args() -> (Option<String>, ReloadMode) {
+    let mut path = None;
+    let mut mode = ReloadMode::Restart;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--choreography" {
+            match args.next() {
+                Some(p) => path = Some(p),
+                None => println!("--choreography needs a value"),
+            }
+        }
+        if arg == "--keep-step" {
+            mode = ReloadMode::KeepStep;
+        }
+    }
+    (path, mode)
+}
+
+fn model(app: &App) -> Model {
+    let _window = app
+        .new_window()
+        .view(view)
+        .size(1024, 1024)
+        .event(window_event)
+        .build()
+        .unwrap();
+    let receiver = osc::receiver(PORT).unwrap();
+    let bridges = Bridges::connect(&bridge_configs()).unwrap();
+
+    let toio = CubeRegistry::new();
+
+    let (path, reload_mode) = choreography_args();
+    let (file, watcher) = match path {
+        Some(path) => match choreography::load(&path) {
+            Ok(file) => (file, Some(ChoreographyWatcher::new(&path))),
+            Err(e) => {
+                println!("{}", e);
+                std::process::exit(1);
+            }
+        },
+        None => (
+            Choreography {
+                tasks: choreography::hexagon(),
+                ..Choreography::default()
+            },
+            None,
+        ),
+    };
+
+    //the file's groups on top of ours, then the command line's, either can give the
+    //default group other cubes
+    let mut groups = CubeGroups::new();
+    groups.insert(DEFAULT_GROUP, vec![CubeId::new(0, 0), CubeId::new(0, 1)]);
+    for (name, cubes) in file.groups.iter() {
+        groups.insert(name, cubes.clone());
+    }
+    let group_args = group_args();
+    for (name, cubes) in group_args.iter() {
+        groups.insert(name, cubes.clone());
+    }
+    let mut engine = Engine::new(groups, vec![TaskQueue::new(DEFAULT_GROUP, file.tasks)]);
+    //cues: the first cube pauses and resumes the routine, the second one skips a task
+    let (first, second) = (CubeId::new(0, 0), CubeId::new(0, 1));
+    engine.on_press(first, ButtonAction::Pause(DEFAULT_GROUP.to_string()));
+    engine.on_press(second, ButtonAction::Skip(DEFAULT_GROUP.to_string()));
+    for id in [first, second] {
+        engine.on_press(
+            id,
+            ButtonAction::Custom(Box::new(|id, queues, _| {
+                for queue in queues.iter() {
+                    println!(
+                        "Button {}: {} at step {}{}",
+                        id,
+                        queue.name,
+                        queue.step(),
+                        match (queue.is_paused(), queue.is_held()) {
+                            (true, _) => " (paused)",
+                            (false, true) => " (waiting for a cube)",
+                            (false, false) => "",
+                        }
+                    );
+                }
+            })),
+        );
+    }
+    Model {
+        receiver,
+
+        bridges,
+        toio,
+        auto_turn: false,
+        go: false,
+        aim_close: false,
+        aim_away: false,
+        aim_target: false,
+        wiggle: false,
+        start_wiggle: 0,
+        engine,
+        watcher,
+        reload_mode,
+        last_reload_check: 0,
+        group_args,
+        overlay: None,
+    }
+}
+fn window_event(app: &App, model: &mut Model, event: WindowEvent) {
+    match event {
+        KeyPressed(key) => {
+            println!("{}", app.elapsed_frames());
+            let now = app.duration.since_start.as_millis() as u64;
+            if let Key::C = key {
+                model.aim_close = !model.aim_close;
+                model.aim_away = false;
+            }
+            if let Key::D = key {
+                model.aim_away = !model.aim_away;
+                model.aim_close = false;
+            }
+            if let Key::T = key {
+                model.aim_target = !model.aim_target;
+                model.aim_close = false;
+                model.aim_away = false;
+            }
+            if let Key::W = key {
+                model.wiggle = !model.wiggle;
+                model.start_wiggle = app.duration.since_start.as_millis() as u64;
+            }
+            if let Key::Q = key {
+                emergency_stop(model, true, now);
+            }
+            if let Key::U = key {
+                emergency_stop(model, false, now);
+            }
+            if let Key::I = key {
+                model.bridges.motor(CubeId::new(0, 1), [20, 20], 20);
+            }
+            if let Key::K = key {
+                model.bridges.motor(CubeId::new(0, 1), [-20, -20], 20);
+            }
+            if let Key::O = key {
+                model.bridges.motor(CubeId::new(0, 2), [20, 20], 20);
+            }
+            if let Key::L = key {
+                model.bridges.motor(CubeId::new(0, 2), [-20, -20], 20);
+            }
+            if let Key::W = key {
+                model.bridges.motor(CubeId::new(0, 0), [30, 30], 50);
+            }
+            if let Key::S = key {
+                model.bridges.motor(CubeId::new(0, 0), [-30, -30], 50);
+            }
+            if let Key::A = key {
+                model.bridges.motor(CubeId::new(0, 0), [-20, 20], 50);
+            }
+            if let Key::D = key {
+                model.bridges.motor(CubeId::new(0, 0), [20, -20], 50);
+            }
+            if let Key::A = key {
+                model.auto_turn = !model.auto_turn;
+            }
+            //routine controls
+            match key {
+                Key::Space if model.engine.is_paused() => {
+                    model.engine.resume(now);
+                    println!("Resumed");
+                }
+                Key::Space => {
+                    model.engine.pause(now);
+                    println!("Paused");
+                }
+                Key::Return => {
+                    model.engine.step_through(now);
+                    println!("One more task");
+                }
+                Key::Right => {
+                    model.engine.skip(now);
+                    println!("Skipped");
+                }
+                Key::Home => {
+                    model.engine.restart(now);
+                    println!("Restarted");
+                }
+                _ => {}
+            }
+            if let Key::R = key {
+                model.reload_mode = match model.reload_mode {
+                    ReloadMode::Restart => ReloadMode::KeepStep,
+                    ReloadMode::KeepStep => ReloadMode::Restart,
+                };
+                println!("Reload mode: {:?}", model.reload_mode);
+            }
+        }
+        KeyReleased(_key) => {}
+        MouseMoved(_pos) => {}
+        MousePressed(_button) => {
+            model.aim_away = false;
+            model.aim_close = false;
+            model.go = true;
+        }
+        MouseReleased(_button) => {
+            model.go = false;
+        }
+        MouseEntered => {}
+        MouseExited => {}
+        MouseWheel(_amount, _phase) => {}
+        Moved(_pos) => {}
+        Resized(_size) => {}
+        Touch(_touch) => {}
+        TouchPressure(_pressure) => {}
+        HoveredFile(_path) => {}
+        DroppedFile(_path) => {}
+        HoveredFileCancelled => {}
+        Focused => {}
+        Unfocused => {}
+        Closed => {}
+    }
+}
+
+//stop every cube we know of on every bridge and hold the routine, or let it go again
+//the routine stays paused after clearing, Space resumes it
+fn emergency_stop(model: &mut Model, engaged: bool, now: u64) {
+    if engaged {
+        let mut cubes: Vec<CubeId> = model.toio.iter().map(|(id, _)| *id).collect();
+        //and the ones we expect but never heard from
+        for (_, group) in model.engine.groups.iter() {
+            for id in group {
+                if !cubes.contains(id) {
+                    cubes.push(*id);
+                }
+            }
+        }
+        model.bridges.emergency_stop(&cubes);
+        model.engine.pause(now);
+        println!("EMERGENCY STOP, U to clear");
+    } else if model.bridges.is_stopped() {
+        model.bridges.clear_emergency_stop();
+        println!("Emergency stop cleared, the routine is still paused");
+    }
+}
+
+fn update(app: &App, model: &mut Model, _update: Update) {
+    let packets: Vec<_> = model.receiver.try_iter().collect();
+    for (packet, _addr) in packets {
+        //println!("{:?}", packet);
+        for message in packet.into_msgs() {
+            match InboundMessage::try_from(message) {
+                Ok(InboundMessage::Position {
+                    host,
+                    cube,
+                    x,
+                    y,
+                    angle,
+                    ..
+                }) => {
+                    //update info in our cube map
+                    let now = app.duration.since_start.as_millis() as u64;
+                    model.toio.update(CubeId::new(host, cube), x, y, angle, now);
+                }
+                Ok(InboundMessage::Button {
+                    host,
+                    cube,
+                    pressed,
+                }) => {
+                    //only presses, not releases, wait_button tasks and cues use them
+                    if pressed {
+                        let now = app.duration.since_start.as_millis() as u64;
+                        model.engine.press(CubeId::new(host, cube), now);
+                    }
+                }
+                Ok(InboundMessage::PositionMissed { host, cube }) => {
+                    //lifted off the mat, no more commands until it's back
+                    println!("Cube {} left the mat", CubeId::new(host, cube));
+                    model.toio.position_missed(CubeId::new(host, cube));
+                }
+                Ok(InboundMessage::StandardId {
+                    host,
+                    cube,
+                    value,
+                    angle,
+                }) => {
+                    let now = app.duration.since_start.as_millis() as u64;
+                    println!("Cube {} read card {}", CubeId::new(host, cube), value);
+                    model
+                        .toio
+                        .read_card(CubeId::new(host, cube), value, angle, now);
+                }
+                Ok(InboundMessage::Motion {
+                    host,
+                    cube,
+                    flat,
+                    collision,
+                    double_tap,
+                    posture,
+                    shake,
+                }) => {
+                    let now = app.duration.since_start.as_millis() as u64;
+                    let id = CubeId::new(host, cube);
+                    let report = MotionReport::new(flat, collision, double_tap, posture, shake);
+                    for event in model.toio.motion(id, report, now) {
+                        println!("Cube {}: {}", id, event);
+                    }
+                }
+                Ok(InboundMessage::Battery { host, cube, level }) => {
+                    model.toio.battery(CubeId::new(host, cube), level);
+                }
+                Ok(InboundMessage::TargetResult {
+                    host,
+                    cube,
+                    request,
+                    result,
+                }) => {
+                    let now = app.duration.since_start.as_millis() as u64;
+                    model
+                        .toio
+                        .target_result(CubeId::new(host, cube), request, result, now);
+                }
+                Ok(InboundMessage::EmergencyStop { engaged }) => {
+                    let now = app.duration.since_start.as_millis() as u64;
+                    emergency_stop(model, engaged, now);
+                }
+                Err(ProtocolError::UnknownAddress(_)) => {}
+                Err(e) => println!("Bad message: {}", e),
+            }
+        }
+    }
+
+    //the logic
+    let now = app.duration.since_start.as_millis() as u64;
+
+    //pick up changes to the choreography file
+    if now >= model.last_reload_check + 500 {
+        model.last_reload_check = now;
+        if let Some(result) = model.watcher.as_mut().and_then(|w| w.poll()) {
+            match result {
+                Ok(file) => {
+                    println!("Choreography reloaded ({:?})", model.reload_mode);
+                    //same order as at the start, the command line wins
+                    for (name, cubes) in file.groups.iter() {
+                        model.engine.groups.insert(name, cubes.clone());
+                    }
+                    for (name, cubes) in model.group_args.iter() {
+                        model.engine.groups.insert(name, cubes.clone());
+                    }
+                    if let Some(queue) = model.engine.queue_mut(DEFAULT_GROUP) {
+                        queue.replace(file.tasks, model.reload_mode, now);
+                    }
+                    model.overlay = None;
+                }
+                Err(e) => {
+                    //keep running the old tasks until the file is fixed
+                    println!("{}", e);
+                    model.overlay = Some(e.to_string());
+                }
+            }
+        }
+    }
+
+    //which cubes went quiet, or came back
+    for event in model.toio.check(now) {
+        println!("Cube {}: {:?} -> {:?}", event.id, event.from, event.to);
+    }
+
+    //run the task at hand of every queue
+    let tick = model.engine.update(&model.toio, now);
+    for (id, what) in tick.motors {
+        model.bridges.motor(id, what, 50);
+    }
+    for (id, command) in tick.lights.into_iter().chain(tick.sounds) {
+        model.bridges.send(id.host, command);
+    }
+    //held back during an emergency stop, the engine sends them again after
+    for (id, command) in tick.targets {
+        if !model.bridges.send(id.host, command) {
+            model.engine.unsent(id);
+        }
+    }
+    for event in tick.events {
+        match event {
+            TaskEvent::Done { queue, .. } => println!("Task done! ({})", queue),
+            TaskEvent::Failed {
+                queue,
+                step,
+                reason,
+                action,
+            } => println!(
+                "Task {} of {} failed: {} ({:?})",
+                step, queue, reason, action
+            ),
+            TaskEvent::Held { queue, cube } => {
+                println!("{} waits for cube {} to come back", queue, cube)
+            }
+            TaskEvent::Released { queue } => println!("{} goes on", queue),
+            TaskEvent::LowBattery { cube, level, spare } => match spare {
+                Some(spare) => {
+                    println!("Cube {} battery at {}%, {} takes over", cube, level, spare)
+                }
+                None => println!("Cube {} battery at {}%, charge it!", cube, level),
+            },
+        }
+    }
+}
+
+fn view(app: &App, model: &Model, frame: Frame) {
+    let draw = app.draw();
+    draw.background().color(BLACK);
+
+    let first = model
+        .engine
+        .groups
+        .get(DEFAULT_GROUP)
+        .and_then(|g| g.first());
+    let now = app.duration.since_start.as_millis() as u64;
+    for (i, cube_data) in model.toio.iter() {
+        let angle = cube_data.angle as f32 / 360.0 * TAU;
+        //what the cube noticed in the last second, over it
+        let motions: Vec<String> = cube_data
+            .motions
+            .iter()
+            .filter(|m| now < m.at + 1000)
+            .map(|m| m.event.to_string())
+            .collect();
+        if !motions.is_empty() {
+            draw.text(&motions.join(", "))
+                .x_y(cube_data.x as f32, cube_data.y as f32 + 25.0)
+                .w_h(200.0, 20.0)
+                .color(ORANGE);
+        }
+        //battery level under the cube, in red once it's low
+        if let Some(level) = cube_data.battery {
+            let color = if level < model.engine.battery.low_below {
+                RED
+            } else {
+                GREY
+            };
+            draw.text(&format!("{}%", level))
+                .x_y(cube_data.x as f32, cube_data.y as f32 - 25.0)
+                .w_h(60.0, 20.0)
+                .color(color);
+        }
+        //where we last saw the cubes that went quiet
+        let body = match cube_data.state {
+            Liveness::Live => srgba(1.0, 1.0, 1.0, 1.0),
+            Liveness::Reconnected => srgba(1.0, 1.0, 0.5, 1.0),
+            Liveness::Stale => srgba(0.5, 0.5, 0.5, 0.6),
+            Liveness::Lost => srgba(0.3, 0.3, 0.3, 0.3),
+        };
+        //off the mat, only an outline where it left
+        if !cube_data.on_mat {
+            draw.rect()
+                .x_y(cube_data.x as f32, cube_data.y as f32)
+                .rotate(angle)
+                .w_h(20.0, 20.0)
+                .no_fill()
+                .stroke(body)
+                .stroke_weight(2.0);
+            continue;
+        }
+        draw.rect()
+            .x_y(cube_data.x as f32, cube_data.y as f32)
+            .rotate(angle)
+            .w_h(20.0, 20.0)
+            .color(body);
+        let c = if Some(i) == first {
+            srgb(1.0, 0.0, 0.0)
+        } else {
+            srgb(0.0, 1.0, 0.0)
+        };
+        draw.rect()
+            .x_y(
+                cube_data.x as f32 + angle.cos() * 5.0,
+                cube_data.y as f32 + angle.sin() * 5.0,
+            )
+            .rotate(angle)
+            .w_h(10.0, 10.0)
+            .color(c);
+    }
+
+    if model.bridges.is_stopped() {
+        let win = app.window_rect();
+        draw.rect()
+            .x_y(0.0, win.bottom() + 40.0)
+            .w_h(win.w(), 80.0)
+            .color(srgba(0.8, 0.0, 0.0, 0.9));
+        draw.text("EMERGENCY STOP - U to clear")
+            .x_y(0.0, win.bottom() + 40.0)
+            .w_h(win.w() - 40.0, 80.0)
+            .font_size(24)
+            .color(WHITE);
+    }
+
+    if let Some(message) = &model.overlay {
+        let win = app.window_rect();
+        draw.rect()
+            .x_y(0.0, win.top() - 40.0)
+            .w_h(win.w(), 80.0)
+            .color(srgba(0.3, 0.0, 0.0, 0.8));
+        draw.text(message)
+            .x_y(0.0, win.top() - 40.0)
+            .w_h(win.w() - 40.0, 80.0)
+            .color(WHITE);
+    }
+
+    draw.to_frame(app, &frame).unwrap();
+}
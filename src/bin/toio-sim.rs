@@ -0,0 +1,76 @@
+//! Headless stand-in for the toio-osc bridge.
+//!
+//! Listens for `/motor` (and `/target`, `/targets`) on `TARGET_PORT`, drives
+//! simulated cubes on a virtual mat and sends their `/position` (and
+//! `/target_result`) to `PORT`, so the nannou app and the task queue can run
+//! without Bluetooth or cubes.
+//!
+//! cargo run --bin toio-sim -- --cubes 2 --host 0
+
+use nannou_osc as osc;
+use std::convert::TryFrom;
+use std::thread;
+use std::time::{Duration, Instant};
+use toio::protocol::{OutboundCommand, ProtocolError, PORT, TARGET_PORT};
+use toio::sim::{Simulator, POSITION_PERIOD};
+
+//ms between two simulation steps
+const STEP: u64 = 5;
+
+fn main() {
+    let mut cubes = 2;
+    let mut host = 0;
+    let mut listen = TARGET_PORT;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args.next().unwrap_or_default();
+        //each in its own type, so that nothing wraps around
+        let parsed = match arg.as_ref() {
+            "--cubes" => value.parse().ok().filter(|n| *n > 0).map(|n| cubes = n),
+            "--host" => value.parse().ok().map(|v| host = v),
+            "--port" => value.parse().ok().map(|v| listen = v),
+            _ => None,
+        };
+        if parsed.is_none() {
+            println!("usage: toio-sim [--cubes N (at least 1)] [--host ID] [--port PORT]");
+            return;
+        }
+    }
+
+    let receiver = osc::receiver(listen).unwrap();
+    let target_addr = format!("{}:{}", "127.0.0.1", PORT);
+    let sender = osc::sender().unwrap().connect(target_addr).unwrap();
+    let mut sim = Simulator::new(host, cubes);
+    println!(
+        "toio-sim: {} cubes on host {}, listening on {}",
+        cubes, host, listen
+    );
+
+    let start = Instant::now();
+    let mut last_step = 0;
+    let mut last_position = 0;
+    loop {
+        let now = start.elapsed().as_millis() as u64;
+        for (packet, _addr) in receiver.try_iter() {
+            for message in packet.into_msgs() {
+                match OutboundCommand::try_from(message) {
+                    Ok(command) => sim.handle(command, now),
+                    Err(ProtocolError::UnknownAddress(_)) => {}
+                    Err(e) => println!("Bad message: {}", e),
+                }
+            }
+        }
+        sim.step(now, now - last_step);
+        last_step = now;
+        for result in sim.results() {
+            sender.send(result).ok();
+        }
+        if now >= last_position + POSITION_PERIOD {
+            for position in sim.positions() {
+                sender.send(position).ok();
+            }
+            last_position = now;
+        }
+        thread::sleep(Duration::from_millis(STEP));
+    }
+}
@@ -0,0 +1,163 @@
+use crate::cube::CubeId;
+use crate::protocol::{OutboundCommand, TARGET_PORT};
+use nannou_osc as osc;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Where to reach the toio-osc bridge with a given host id.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BridgeConfig {
+    pub host: i32,
+    pub address: String,
+    pub port: u16,
+}
+
+impl Default for BridgeConfig {
+    fn default() -> Self {
+        BridgeConfig {
+            host: 0,
+            address: "127.0.0.1".to_string(),
+            port: TARGET_PORT,
+        }
+    }
+}
+
+impl BridgeConfig {
+    pub fn target_address_string(&self) -> String {
+        format!("{}:{}", self.address, self.port)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BridgeConfigError(pub String);
+
+impl fmt::Display for BridgeConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "bad bridge \"{}\", expected host=address:port (e.g. 1=127.0.0.1:3334)",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for BridgeConfigError {}
+
+//host=address:port, or host=address to use TARGET_PORT
+impl FromStr for BridgeConfig {
+    type Err = BridgeConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || BridgeConfigError(s.to_string());
+        let mut parts = s.splitn(2, '=');
+        let host = parts.next().unwrap_or("");
+        let target = parts.next().ok_or_else(err)?;
+        let host = host.trim().parse().map_err(|_| err())?;
+        let (address, port) = match target.rfind(':') {
+            Some(i) => (&target[..i], target[i + 1..].parse().map_err(|_| err())?),
+            None => (target, TARGET_PORT),
+        };
+        if address.is_empty() {
+            return Err(err());
+        }
+        Ok(BridgeConfig {
+            host,
+            address: address.to_string(),
+            port,
+        })
+    }
+}
+
+/// One connected sender per bridge, commands are routed by host id.
+///
+/// After `emergency_stop`, every command that moves cubes is dropped until
+/// `clear_emergency_stop`.
+pub struct Bridges {
+    senders: HashMap<i32, osc::Sender<osc::Connected>>,
+    stopped: bool,
+}
+
+impl Bridges {
+    pub fn connect(configs: &[BridgeConfig]) -> Result<Self, std::io::Error> {
+        let mut senders = HashMap::new();
+        for config in configs {
+            let sender = osc::sender()?.connect(config.target_address_string())?;
+            senders.insert(config.host, sender);
+        }
+        Ok(Bridges {
+            senders,
+            stopped: false,
+        })
+    }
+    pub fn hosts(&self) -> impl Iterator<Item = &i32> {
+        self.senders.keys()
+    }
+    //false if we don't know this host, the send failed or the cubes are stopped
+    pub fn send(&self, host: i32, command: OutboundCommand) -> bool {
+        if self.stopped && command.moves_cubes() {
+            return false;
+        }
+        self.send_anyway(host, command)
+    }
+    fn send_anyway(&self, host: i32, command: OutboundCommand) -> bool {
+        match self.senders.get(&host) {
+            Some(sender) => sender.send(command).is_ok(),
+            None => false,
+        }
+    }
+    //stop these cubes right away and hold back any motor command until cleared
+    pub fn emergency_stop<'a, I: IntoIterator<Item = &'a CubeId>>(&mut self, cubes: I) {
+        self.stopped = true;
+        for id in cubes {
+            //a duration of 0 has no time limit
+            self.send_anyway(id.host, OutboundCommand::motor(id.cube, [0, 0], 0));
+        }
+    }
+    pub fn clear_emergency_stop(&mut self) {
+        self.stopped = false;
+    }
+    pub fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+    //send a /motor to the bridge that owns the cube
+    pub fn motor(&self, id: CubeId, speeds: [i32; 2], duration: i32) -> bool {
+        self.send(id.host, OutboundCommand::motor(id.cube, speeds, duration))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn emergency_stop_holds_back_motors() {
+        //a port of our own playing the bridge, whichever is free
+        let receiver = osc::receiver(0).unwrap();
+        let port = receiver.local_addr().unwrap().port();
+        let config = BridgeConfig {
+            port,
+            ..BridgeConfig::default()
+        };
+        let mut bridges = Bridges::connect(&[config]).unwrap();
+        let received = || -> Vec<OutboundCommand> {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            receiver
+                .try_iter()
+                .flat_map(|(packet, _)| packet.into_msgs())
+                .filter_map(|m| OutboundCommand::try_from(m).ok())
+                .collect()
+        };
+
+        let id = CubeId::new(0, 1);
+        bridges.emergency_stop(&[id]);
+        assert_eq!(received(), vec![OutboundCommand::motor(1, [0, 0], 0)]);
+        assert!(!bridges.motor(id, [50, 50], 100));
+        assert!(received().is_empty());
+
+        bridges.clear_emergency_stop();
+        assert!(bridges.motor(id, [50, 50], 100));
+        assert_eq!(received(), vec![OutboundCommand::motor(1, [50, 50], 100)]);
+    }
+}
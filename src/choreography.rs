@@ -0,0 +1,1440 @@
+//! Task lists for the task queue, built in code or loaded from a file.
+//!
+//! A choreography file is a list of `tasks`, run in order. Each task has a `task` kind,
+//! its parameters and an optional `group` (the default group otherwise). Positions are
+//! `[x, y]` in the app frame (centered on the mat, y up), angles are in degrees.
+//!
+//! The `groups` the tasks use are listed as `[host, cube]` pairs, so that cubes of any
+//! bridge can be in them. A task on a group that isn't there, or needing more cubes than
+//! it has (a `cube` index past its end included), doesn't load. The default group can be
+//! given too, the app has its own otherwise.
+//!
+//! ```ron
+//! (
+//!     groups: {"left": [[0, 0]], "right": [[1, 0], [1, 1]]},
+//!     tasks: [
+//!         (task: "get_close"),
+//!         (task: "move_shift", to: [80.0, 0.0], distance: 15.0),
+//!         (task: "spin", duration: 1000, power: 0.3, group: "left"),
+//!     ],
+//! )
+//! ```
+//!
+//! `move_to` and `follow_path` let the cubes drive themselves: cube i goes to
+//! `targets[i]`, or through every point of `paths[i]`, and the task is done when the
+//! cubes say they got there. A point is `(at: [x, y])`, with an `angle` to end facing
+//! that way. They take a `movement` (`"curve"`, the default, `"curve_forward"` or
+//! `"turn_first"`), a `max_speed` (10 to 115, 80 by default), a `speed_change`
+//! (`"constant"`, the default, `"accelerate"`, `"decelerate"` or
+//! `"accelerate_decelerate"`) and a `cube_timeout` in s after which the cubes give up
+//! (10 by default).
+//!
+//! ```ron
+//! (task: "follow_path", movement: "turn_first", paths: [
+//!     [(at: [-100.0, 0.0]), (at: [-100.0, 100.0], angle: 90.0)],
+//!     [(at: [100.0, 0.0]), (at: [100.0, 100.0], angle: 90.0)],
+//! ])
+//! ```
+//!
+//! Any task can also take a `timeout` in ms and an `on_failure` policy for when it runs
+//! out: `"skip"` (the default), `"abort"` the queue, `{"retry": 3}` or
+//! `{"recover": [tasks]}`. With `abort_on: ["collision"]` it also fails as soon as a
+//! cube of its group notices one of these motions: `"flat"`, `"tilted"`,
+//! `"collision"`, `"double_tap"`, `"posture"` or `"shake"`.
+//!
+//! Tasks can be put together: `sequence` runs its `tasks` one after the other,
+//! `parallel` runs them all at once and is done when `join` is `"all"` (the default) or
+//! `"any"` of them is, `repeat` runs its `tasks` `times` times (forever without it) and
+//! `loop_until` runs its `tasks` over and over until the `until` task is done.
+//!
+//! ```ron
+//! (task: "loop_until", until: (task: "wait", duration: 10000), tasks: [
+//!     (task: "spin", duration: 1000, power: 0.3),
+//!     (task: "wiggle", duration: 500, power: 0.5),
+//! ])
+//! ```
+//!
+//! `(task: "wait_button", cube: 0)` waits for the button of the first cube of the group
+//! to be pressed, of any cube of the group without `cube`.
+//!
+//! `(task: "wait_card", card: 3)` waits for a cube of the group to be put on the card
+//! (or sticker) with that standard id, any card without `card`, and takes `cube` too.
+//! `(task: "wait_motion", motion: "double_tap")` waits for a cube of the group to notice
+//! that motion, and takes `cube` too.
+//! `branch_on_card` waits for a card and goes on with the `tasks` of its case, or
+//! `otherwise` when no case has that card (it keeps waiting without `otherwise`).
+//!
+//! ```ron
+//! (task: "branch_on_card", cases: [
+//!     (card: 1, tasks: [(task: "spin", duration: 1000, power: 0.3)]),
+//!     (card: 2, tasks: [(task: "get_close")]),
+//! ], otherwise: [(task: "wiggle", duration: 500, power: 0.5)])
+//! ```
+//!
+//! `(task: "set_light", color: [255, 0, 0], duration: 1000)` lights the cubes of the
+//! group (for good without `duration`) and goes right on with the next task, so the
+//! cubes stay lit while they move. `light_sequence` goes through its `steps`, `repeat`
+//! times (forever without it):
+//!
+//! ```ron
+//! (task: "light_sequence", repeat: 3, steps: [
+//!     (color: [255, 0, 0], duration: 200),
+//!     (color: [0, 0, 255], duration: 200),
+//! ])
+//! ```
+//!
+//! `(task: "play_sound", effect: 3)` plays one of the sound effects of the cubes (0 to
+//! 10) and `play_notes` plays a melody, see `melody` for how to write it. `volume` goes
+//! up to 255 (the default), notes last `duration` ms (250 by default) unless they say
+//! otherwise and the melody plays `repeat` times (forever without it). They go right
+//! on with the next task too.
+//!
+//! ```ron
+//! (task: "play_notes", melody: "C4 E4 G4 C5:500", volume: 128, repeat: 2)
+//! ```
+//!
+//! The same structure works in JSON and TOML (`[[tasks]]` tables), the format is
+//! picked from the file extension.
+
+use crate::cube::{CubeGroups, CubeId, MotionEvent};
+use crate::kinematics::{DEAD_ZONE, MAX_SPEED};
+use crate::melody::melody;
+use crate::protocol::{LightStep, MovementType, SpeedChange, TargetConfig};
+use crate::protocol::{MAX_LIGHT_MS, MAX_SOUND_EFFECT};
+use crate::toiotask::{Join, OnFailure, TaskType, ToioTask, Waypoint};
+use crate::toiotask::{DEFAULT_GROUP, DIST_AWAY, DIST_CLOSE};
+use nannou::prelude::*;
+use serde::de::value::MapAccessDeserializer;
+use serde::de::{MapAccess, Visitor};
+use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+//the pair gets close, then moves its center around shrinking hexagons
+pub fn hexagon() -> Vec<ToioTask> {
+    let mut tasks = Vec::new();
+
+    tasks.push(ToioTask::new_get_close(DIST_CLOSE));
+    let mut round = Vec::new();
+    let ni = 4;
+    let nj = 6;
+    for i in 0..ni {
+        let fi = i as f32 / ni as f32;
+        for j in 0..nj {
+            let fj = j as f32 / nj as f32;
+            let angle = fj * TAU;
+            let r = (0.4 + 0.6 * (1.0 - fi)) * 80.0;
+            round.push(ToioTask::new_pair_move_shift(
+                angle.cos() * r,
+                angle.sin() * r,
+                15.0,
+            ));
+            if i + j == 0 {
+                round.push(ToioTask::new_get_away(DIST_AWAY));
+            }
+        }
+    }
+    round.push(ToioTask::new_get_close(DIST_CLOSE));
+    tasks.push(ToioTask::new_repeat(ToioTask::new_sequence(round), 1));
+    tasks
+}
+
+/// What a choreography file holds.
+#[derive(Default)]
+pub struct Choreography {
+    pub tasks: Vec<ToioTask>,
+    //the groups of the file only, put them in the engine on top of the app's
+    pub groups: CubeGroups,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Ron,
+    Json,
+    Toml,
+}
+
+impl Format {
+    pub fn from_path(path: &Path) -> Option<Format> {
+        match path.extension()?.to_str()? {
+            "ron" => Some(Format::Ron),
+            "json" => Some(Format::Json),
+            "toml" => Some(Format::Toml),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ChoreographyError {
+    Io(PathBuf, io::Error),
+    UnknownFormat(PathBuf),
+    /// line and column start at 1, 0 when the parser could not tell
+    Invalid {
+        path: Option<PathBuf>,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for ChoreographyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChoreographyError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            ChoreographyError::UnknownFormat(path) => write!(
+                f,
+                "{}: unknown choreography format, use .ron, .json or .toml",
+                path.display()
+            ),
+            ChoreographyError::Invalid {
+                path,
+                line,
+                column,
+                message,
+            } => {
+                if let Some(path) = path {
+                    write!(f, "{}:", path.display())?;
+                }
+                write!(f, "{}:{}: {}", line, column, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ChoreographyError {}
+
+//read a choreography file, the format comes from the extension
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Choreography, ChoreographyError> {
+    let path = path.as_ref();
+    let format =
+        Format::from_path(path).ok_or_else(|| ChoreographyError::UnknownFormat(path.into()))?;
+    let text = fs::read_to_string(path).map_err(|e| ChoreographyError::Io(path.into(), e))?;
+    parse(&text, format).map_err(|e| match e {
+        ChoreographyError::Invalid {
+            line,
+            column,
+            message,
+            ..
+        } => ChoreographyError::Invalid {
+            path: Some(path.into()),
+            line,
+            column,
+            message,
+        },
+        e => e,
+    })
+}
+
+/// Notices when a choreography file changes on disk and loads it again.
+pub struct ChoreographyWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+impl ChoreographyWatcher {
+    //the file as it is now counts as already loaded
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        let path = path.as_ref().to_path_buf();
+        let modified = modified(&path);
+        ChoreographyWatcher { path, modified }
+    }
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    //the new tasks and groups (or why they can't be loaded) when the file changed since
+    //the last call
+    pub fn poll(&mut self) -> Option<Result<Choreography, ChoreographyError>> {
+        //a missing file is most likely an editor in the middle of saving it
+        let modified = modified(&self.path)?;
+        if Some(modified) == self.modified {
+            return None;
+        }
+        self.modified = Some(modified);
+        Some(load(&self.path))
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+//serde_json and toml append the position to their messages
+fn without_position(message: String) -> String {
+    match message.rfind(" at line ") {
+        Some(i) => message[..i].to_string(),
+        None => message,
+    }
+}
+
+pub fn parse(text: &str, format: Format) -> Result<Choreography, ChoreographyError> {
+    let file: File = match format {
+        Format::Ron => ron::from_str(text).map_err(|e| ChoreographyError::Invalid {
+            path: None,
+            line: e.position.line,
+            column: e.position.col,
+            message: e.code.to_string(),
+        })?,
+        Format::Json => serde_json::from_str(text).map_err(|e| ChoreographyError::Invalid {
+            path: None,
+            line: e.line(),
+            column: e.column(),
+            message: without_position(e.to_string()),
+        })?,
+        Format::Toml => toml::from_str(text).map_err(|e| {
+            let (line, column) = e.line_col().map_or((0, 0), |(l, c)| (l + 1, c + 1));
+            ChoreographyError::Invalid {
+                path: None,
+                line,
+                column,
+                message: without_position(e.to_string()),
+            }
+        })?,
+    };
+    let mut groups = CubeGroups::new();
+    for (name, cubes) in file.groups {
+        let cubes = not_empty("groups", cubes)
+            .and_then(|cubes| cubes.into_iter().map(cube_id).collect())
+            .map_err(|e| unplaced(format!("group \"{}\": {}", name, e)))?;
+        if name.is_empty() {
+            return Err(unplaced("group names can't be empty".to_string()));
+        }
+        groups.insert(&name, cubes);
+    }
+    let tasks: Vec<ToioTask> = file.tasks.into_iter().map(|t| t.0).collect();
+    for (i, task) in tasks.iter().enumerate() {
+        let mut used = Vec::new();
+        used_groups(task, &mut used);
+        //the default group is the app's, it knows how many cubes it has
+        for (name, needed) in used.into_iter().filter(|(g, _)| *g != DEFAULT_GROUP) {
+            let cubes = match groups.get(name) {
+                Some(cubes) => cubes.len(),
+                None => {
+                    return Err(unplaced(format!(
+                        "task {} uses group \"{}\", which isn't in groups",
+                        i + 1,
+                        name
+                    )))
+                }
+            };
+            if cubes < needed {
+                return Err(unplaced(format!(
+                    "task {} needs {} cubes of group \"{}\", which has {}",
+                    i + 1,
+                    needed,
+                    name,
+                    cubes
+                )));
+            }
+        }
+    }
+    Ok(Choreography { tasks, groups })
+}
+
+//for what is only known once the whole file is read
+fn unplaced(message: String) -> ChoreographyError {
+    ChoreographyError::Invalid {
+        path: None,
+        line: 0,
+        column: 0,
+        message,
+    }
+}
+
+//every group the task and the ones in it run on, with how many cubes they need of it
+fn used_groups<'a>(task: &'a ToioTask, used: &mut Vec<(&'a str, usize)>) {
+    //through the trait, our Task is the one read from the file
+    let needed = crate::toiotask::Task::cubes_needed(task);
+    used.push((&task.group, needed));
+    if let OnFailure::Recover(tasks) = &task.on_failure {
+        tasks.iter().for_each(|t| used_groups(t, used));
+    }
+    match &task.what {
+        TaskType::Sequence(tasks) | TaskType::Parallel { tasks, .. } => {
+            tasks.iter().for_each(|t| used_groups(t, used));
+        }
+        TaskType::Repeat { task, .. } => used_groups(task, used),
+        TaskType::LoopUntil { task, until } => {
+            used_groups(task, used);
+            used_groups(until, used);
+        }
+        TaskType::BranchOnCard { cases, otherwise } => {
+            cases.iter().for_each(|(_, t)| used_groups(t, used));
+            if let Some(otherwise) = otherwise {
+                used_groups(otherwise, used);
+            }
+        }
+        _ => {}
+    }
+}
+
+//ron reads an [i32; 2] as a tuple outside of the tasks, hence the Vec
+fn cube_id(pair: Vec<i32>) -> Result<CubeId, String> {
+    match pair[..] {
+        [host, cube] if host >= 0 && cube >= 0 => Ok(CubeId::new(host, cube)),
+        _ => Err(format!("cubes are [host, cube], got {:?}", pair)),
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct File {
+    //name to [host, cube] pairs
+    #[serde(default)]
+    groups: BTreeMap<String, Vec<Vec<i32>>>,
+    tasks: Vec<Task>,
+}
+
+//checked while deserializing so that the parser reports where the task is
+#[derive(Deserialize)]
+#[serde(try_from = "AnyMap<TaskSpec>")]
+struct Task(ToioTask);
+
+//ron gives a struct with flattened fields a {map} only, this takes a (struct) as well
+struct AnyMap<T>(T);
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for AnyMap<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct MapVisitor<T>(PhantomData<T>);
+        impl<'de, T: Deserialize<'de>> Visitor<'de> for MapVisitor<T> {
+            type Value = AnyMap<T>;
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a map or a struct")
+            }
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                T::deserialize(MapAccessDeserializer::new(map)).map(AnyMap)
+            }
+        }
+        deserializer.deserialize_any(MapVisitor(PhantomData))
+    }
+}
+
+//a task is its kind and what every task takes, the common part goes first so that the
+//kind doesn't see its fields
+#[derive(Deserialize)]
+struct TaskSpec {
+    #[serde(flatten)]
+    common: CommonSpec,
+    #[serde(flatten)]
+    kind: KindSpec,
+}
+
+//the group, a timeout in ms, what to do when it runs out and the motions that make it fail
+#[derive(Deserialize)]
+struct CommonSpec {
+    group: Option<String>,
+    timeout: Option<u64>,
+    on_failure: Option<FailureSpec>,
+    abort_on: Option<Vec<MotionSpec>>,
+}
+
+//the task itself, picked by its "task" field
+#[derive(Deserialize)]
+#[serde(tag = "task", rename_all = "snake_case", deny_unknown_fields)]
+enum KindSpec {
+    //cube i goes to targets[i]
+    Move {
+        targets: Vec<[f32; 2]>,
+        distance: f32,
+    },
+    //the group moves its center, keeping its shape
+    MoveShift {
+        to: [f32; 2],
+        distance: f32,
+    },
+    SingleMove {
+        to: [f32; 2],
+        distance: f32,
+    },
+    Spin {
+        duration: u64,
+        power: f32,
+    },
+    Wiggle {
+        duration: u64,
+        power: f32,
+    },
+    Wait {
+        duration: u64,
+    },
+    GetClose {
+        distance: Option<f32>,
+    },
+    GetAway {
+        distance: Option<f32>,
+    },
+    TargetAngle {
+        angle: f32,
+        tolerance: f32,
+    },
+    TargetAngles {
+        angles: Vec<f32>,
+        tolerance: f32,
+    },
+    //cube i drives itself to targets[i]
+    MoveTo {
+        targets: Vec<WaypointSpec>,
+        movement: Option<MovementSpec>,
+        max_speed: Option<u8>,
+        speed_change: Option<SpeedChangeSpec>,
+        cube_timeout: Option<u8>,
+    },
+    //cube i drives itself through paths[i]
+    FollowPath {
+        paths: Vec<Vec<WaypointSpec>>,
+        movement: Option<MovementSpec>,
+        max_speed: Option<u8>,
+        speed_change: Option<SpeedChangeSpec>,
+        cube_timeout: Option<u8>,
+    },
+    Sync {
+        name: String,
+    },
+    //cube is the index in the group, any cube of the group without it
+    WaitButton {
+        cube: Option<usize>,
+    },
+    //any card without card, cube as in wait_button
+    WaitCard {
+        card: Option<u32>,
+        cube: Option<usize>,
+    },
+    WaitMotion {
+        motion: MotionSpec,
+        cube: Option<usize>,
+    },
+    BranchOnCard {
+        cases: Vec<CaseSpec>,
+        otherwise: Option<Vec<Task>>,
+    },
+    //for good without duration
+    SetLight {
+        color: [u8; 3],
+        duration: Option<u64>,
+    },
+    //forever without repeat
+    LightSequence {
+        steps: Vec<LightStepSpec>,
+        repeat: Option<u32>,
+    },
+    //as loud as it goes without volume
+    PlaySound {
+        effect: u8,
+        volume: Option<u8>,
+    },
+    //duration is for the notes that don't say, forever without repeat
+    PlayNotes {
+        melody: String,
+        duration: Option<u64>,
+        volume: Option<u8>,
+        repeat: Option<u32>,
+    },
+    Sequence {
+        tasks: Vec<Task>,
+    },
+    Parallel {
+        tasks: Vec<Task>,
+        join: Option<JoinSpec>,
+    },
+    //forever without times
+    Repeat {
+        tasks: Vec<Task>,
+        times: Option<u32>,
+    },
+    LoopUntil {
+        until: Box<Task>,
+        tasks: Vec<Task>,
+    },
+}
+
+//what a branch_on_card does for one card
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CaseSpec {
+    card: u32,
+    tasks: Vec<Task>,
+}
+
+//a colour for duration ms
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LightStepSpec {
+    color: [u8; 3],
+    duration: u64,
+}
+
+//a point the cube drives to, angle in degrees
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WaypointSpec {
+    at: [f32; 2],
+    angle: Option<f32>,
+}
+
+//movement: "curve", "curve_forward" or "turn_first"
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum MovementSpec {
+    Curve,
+    CurveForward,
+    TurnFirst,
+}
+
+impl From<MovementSpec> for MovementType {
+    fn from(spec: MovementSpec) -> Self {
+        match spec {
+            MovementSpec::Curve => MovementType::Curve,
+            MovementSpec::CurveForward => MovementType::CurveForward,
+            MovementSpec::TurnFirst => MovementType::TurnFirst,
+        }
+    }
+}
+
+//speed_change: "constant", "accelerate", "decelerate" or "accelerate_decelerate"
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum SpeedChangeSpec {
+    Constant,
+    Accelerate,
+    Decelerate,
+    AccelerateDecelerate,
+}
+
+impl From<SpeedChangeSpec> for SpeedChange {
+    fn from(spec: SpeedChangeSpec) -> Self {
+        match spec {
+            SpeedChangeSpec::Constant => SpeedChange::Constant,
+            SpeedChangeSpec::Accelerate => SpeedChange::Accelerate,
+            SpeedChangeSpec::Decelerate => SpeedChange::Decelerate,
+            SpeedChangeSpec::AccelerateDecelerate => SpeedChange::AccelerateDecelerate,
+        }
+    }
+}
+
+//what the motion sensor of a cube noticed, see MotionEvent
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum MotionSpec {
+    Flat,
+    Tilted,
+    Collision,
+    DoubleTap,
+    Posture,
+    Shake,
+}
+
+impl From<MotionSpec> for MotionEvent {
+    fn from(spec: MotionSpec) -> Self {
+        match spec {
+            MotionSpec::Flat => MotionEvent::Flat,
+            MotionSpec::Tilted => MotionEvent::Tilted,
+            MotionSpec::Collision => MotionEvent::Collision,
+            MotionSpec::DoubleTap => MotionEvent::DoubleTap,
+            MotionSpec::Posture => MotionEvent::Posture,
+            MotionSpec::Shake => MotionEvent::Shake,
+        }
+    }
+}
+
+//join: "all" or "any"
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum JoinSpec {
+    All,
+    Any,
+}
+
+impl From<JoinSpec> for Join {
+    fn from(spec: JoinSpec) -> Self {
+        match spec {
+            JoinSpec::All => Join::All,
+            JoinSpec::Any => Join::Any,
+        }
+    }
+}
+
+//on_failure: "skip", "abort", {"retry": 3} or {"recover": [tasks]}
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum FailureSpec {
+    Skip,
+    Retry(u32),
+    Abort,
+    Recover(Vec<Task>),
+}
+
+impl From<FailureSpec> for OnFailure {
+    fn from(spec: FailureSpec) -> Self {
+        match spec {
+            FailureSpec::Skip => OnFailure::Skip,
+            FailureSpec::Retry(n) => OnFailure::Retry(n),
+            FailureSpec::Abort => OnFailure::AbortQueue,
+            FailureSpec::Recover(tasks) => {
+                OnFailure::Recover(tasks.into_iter().map(|t| t.0).collect())
+            }
+        }
+    }
+}
+
+fn positive(what: &str, value: f32) -> Result<f32, String> {
+    if value.is_finite() && value > 0.0 {
+        Ok(value)
+    } else {
+        Err(format!("{} must be positive, got {}", what, value))
+    }
+}
+
+fn positive_ms(what: &str, ms: u64) -> Result<u64, String> {
+    if ms > 0 {
+        Ok(ms)
+    } else {
+        Err(format!("{} must be positive, got 0", what))
+    }
+}
+
+//how long the notes of a melody last when they don't say
+const NOTE_MS: u64 = 250;
+
+//how long the cube can keep a colour
+fn light_ms(ms: u64) -> Result<i32, String> {
+    if ms <= MAX_LIGHT_MS as u64 {
+        Ok(ms as i32)
+    } else {
+        Err(format!(
+            "duration must be at most {}, got {}",
+            MAX_LIGHT_MS, ms
+        ))
+    }
+}
+
+fn finite(what: &str, value: f32) -> Result<f32, String> {
+    if value.is_finite() {
+        Ok(value)
+    } else {
+        Err(format!("{} must be a number, got {}", what, value))
+    }
+}
+
+fn not_empty<T>(what: &str, values: Vec<T>) -> Result<Vec<T>, String> {
+    if values.is_empty() {
+        Err(format!("{} can't be empty", what))
+    } else {
+        Ok(values)
+    }
+}
+
+fn point(what: &str, p: [f32; 2]) -> Result<Vector2, String> {
+    Ok(vec2(finite(what, p[0])?, finite(what, p[1])?))
+}
+
+fn waypoint(spec: WaypointSpec) -> Result<Waypoint, String> {
+    let at = point("at", spec.at)?;
+    let angle = match spec.angle {
+        Some(angle) => Some(finite("angle", angle)?.to_radians()),
+        None => None,
+    };
+    Ok(Waypoint::new(at.x, at.y, angle))
+}
+
+fn target_config(
+    movement: Option<MovementSpec>,
+    max_speed: Option<u8>,
+    speed_change: Option<SpeedChangeSpec>,
+    cube_timeout: Option<u8>,
+) -> Result<TargetConfig, String> {
+    let default = TargetConfig::default();
+    let max_speed = max_speed.unwrap_or(default.max_speed);
+    if (max_speed as i32) < DEAD_ZONE || max_speed as i32 > MAX_SPEED {
+        return Err(format!(
+            "max_speed goes from {} to {}, got {}",
+            DEAD_ZONE, MAX_SPEED, max_speed
+        ));
+    }
+    Ok(TargetConfig {
+        movement: movement.map_or(default.movement, MovementType::from),
+        max_speed,
+        speed_change: speed_change.map_or(default.speed_change, SpeedChange::from),
+        timeout: cube_timeout.unwrap_or(default.timeout),
+    })
+}
+
+//what a repeat or a loop goes through, a sequence when there's more than one task
+fn body(tasks: Vec<Task>) -> Result<ToioTask, String> {
+    let mut tasks: Vec<ToioTask> = not_empty("tasks", tasks)?
+        .into_iter()
+        .map(|t| t.0)
+        .collect();
+    if tasks.len() == 1 {
+        Ok(tasks.remove(0))
+    } else {
+        Ok(ToioTask::new_sequence(tasks))
+    }
+}
+
+impl TryFrom<AnyMap<TaskSpec>> for Task {
+    type Error = String;
+    fn try_from(AnyMap(spec): AnyMap<TaskSpec>) -> Result<Self, Self::Error> {
+        let TaskSpec { common, kind } = spec;
+        let CommonSpec {
+            group,
+            timeout,
+            on_failure,
+            abort_on,
+        } = common;
+        let mut task = match kind {
+            KindSpec::Move { targets, distance } => {
+                let targets = not_empty("targets", targets)?
+                    .into_iter()
+                    .map(|t| point("targets", t))
+                    .collect::<Result<_, _>>()?;
+                ToioTask::new_group_move(targets, positive("distance", distance)?)
+            }
+            KindSpec::MoveShift { to, distance } => {
+                let to = point("to", to)?;
+                ToioTask::new_pair_move_shift(to.x, to.y, positive("distance", distance)?)
+            }
+            KindSpec::SingleMove { to, distance } => {
+                let to = point("to", to)?;
+                ToioTask::new_single_move(to.x, to.y, positive("distance", distance)?)
+            }
+            KindSpec::Spin { duration, power } => {
+                ToioTask::new_spin(positive_ms("duration", duration)?, finite("power", power)?)
+            }
+            KindSpec::Wiggle { duration, power } => {
+                ToioTask::new_wiggle(positive_ms("duration", duration)?, finite("power", power)?)
+            }
+            KindSpec::Wait { duration } => ToioTask::new_wait(positive_ms("duration", duration)?),
+            KindSpec::GetClose { distance } => {
+                ToioTask::new_get_close(positive("distance", distance.unwrap_or(DIST_CLOSE))?)
+            }
+            KindSpec::GetAway { distance } => {
+                ToioTask::new_get_away(positive("distance", distance.unwrap_or(DIST_AWAY))?)
+            }
+            KindSpec::TargetAngle { angle, tolerance } => ToioTask::new_target_angle(
+                finite("angle", angle)?.to_radians(),
+                positive("tolerance", tolerance)?.to_radians(),
+            ),
+            KindSpec::TargetAngles { angles, tolerance } => {
+                let angles = not_empty("angles", angles)?
+                    .into_iter()
+                    .map(|a| finite("angles", a).map(f32::to_radians))
+                    .collect::<Result<_, _>>()?;
+                ToioTask::new_group_target_angles(
+                    angles,
+                    positive("tolerance", tolerance)?.to_radians(),
+                )
+            }
+            KindSpec::MoveTo {
+                targets,
+                movement,
+                max_speed,
+                speed_change,
+                cube_timeout,
+            } => {
+                let targets = not_empty("targets", targets)?
+                    .into_iter()
+                    .map(waypoint)
+                    .collect::<Result<_, _>>()?;
+                let config = target_config(movement, max_speed, speed_change, cube_timeout)?;
+                ToioTask::new_move_to(targets, config)
+            }
+            KindSpec::FollowPath {
+                paths,
+                movement,
+                max_speed,
+                speed_change,
+                cube_timeout,
+            } => {
+                let paths = not_empty("paths", paths)?
+                    .into_iter()
+                    .map(|path| {
+                        not_empty("paths", path)?
+                            .into_iter()
+                            .map(waypoint)
+                            .collect::<Result<_, _>>()
+                    })
+                    .collect::<Result<_, _>>()?;
+                let config = target_config(movement, max_speed, speed_change, cube_timeout)?;
+                ToioTask::new_follow_path(paths, config)
+            }
+            KindSpec::Sync { name } => {
+                if name.is_empty() {
+                    return Err("sync name can't be empty".to_string());
+                }
+                ToioTask::new_sync(&name)
+            }
+            KindSpec::WaitButton { cube } => ToioTask::new_wait_button(cube),
+            KindSpec::WaitCard { card, cube } => ToioTask::new_wait_card(card, cube),
+            KindSpec::WaitMotion { motion, cube } => ToioTask::new_wait_motion(motion.into(), cube),
+            KindSpec::BranchOnCard { cases, otherwise } => {
+                let cases = not_empty("cases", cases)?
+                    .into_iter()
+                    .map(|case| Ok((case.card, body(case.tasks)?)))
+                    .collect::<Result<_, String>>()?;
+                ToioTask::new_branch_on_card(cases, otherwise.map(body).transpose()?)
+            }
+            KindSpec::SetLight {
+                color: [r, g, b],
+                duration,
+            } => ToioTask::new_set_light(r, g, b, light_ms(duration.unwrap_or(0))?),
+            KindSpec::LightSequence { steps, repeat } => {
+                let steps = not_empty("steps", steps)?
+                    .into_iter()
+                    .map(|step| {
+                        let [r, g, b] = step.color;
+                        let duration = light_ms(positive_ms("duration", step.duration)?)?;
+                        Ok(LightStep::new(r, g, b, duration))
+                    })
+                    .collect::<Result<_, String>>()?;
+                match repeat {
+                    Some(0) => return Err("repeat must be positive, got 0".to_string()),
+                    repeat => ToioTask::new_light_sequence(steps, repeat),
+                }
+            }
+            KindSpec::PlaySound { effect, volume } => {
+                if effect > MAX_SOUND_EFFECT {
+                    return Err(format!(
+                        "effect goes from 0 to {}, got {}",
+                        MAX_SOUND_EFFECT, effect
+                    ));
+                }
+                ToioTask::new_play_sound(effect, volume.unwrap_or(255))
+            }
+            KindSpec::PlayNotes {
+                melody: text,
+                duration,
+                volume,
+                repeat,
+            } => {
+                let duration = positive_ms("duration", duration.unwrap_or(NOTE_MS))?;
+                let duration = i32::try_from(duration).unwrap_or(i32::MAX);
+                let notes = not_empty("melody", melody(&text, duration, volume.unwrap_or(255))?)?;
+                match repeat {
+                    Some(0) => return Err("repeat must be positive, got 0".to_string()),
+                    repeat => ToioTask::new_play_notes(notes, repeat),
+                }
+            }
+            KindSpec::Sequence { tasks } => ToioTask::new_sequence(
+                not_empty("tasks", tasks)?
+                    .into_iter()
+                    .map(|t| t.0)
+                    .collect(),
+            ),
+            KindSpec::Parallel { tasks, join } => ToioTask::new_parallel(
+                not_empty("tasks", tasks)?
+                    .into_iter()
+                    .map(|t| t.0)
+                    .collect(),
+                join.map_or(Join::All, Join::from),
+            ),
+            KindSpec::Repeat { tasks, times } => match times {
+                Some(0) => return Err("times must be positive, got 0".to_string()),
+                Some(times) => ToioTask::new_repeat(body(tasks)?, times),
+                None => ToioTask::new_forever(body(tasks)?),
+            },
+            KindSpec::LoopUntil { until, tasks } => ToioTask::new_loop_until(body(tasks)?, until.0),
+        };
+        match group {
+            Some(group) if group.is_empty() => return Err("group can't be empty".to_string()),
+            Some(group) => task = task.on(&group),
+            None => {}
+        }
+        if let Some(timeout) = timeout {
+            task = task.timeout(positive_ms("timeout", timeout)?);
+        }
+        for motion in abort_on.unwrap_or_default() {
+            task = task.abort_on(motion.into());
+        }
+        if let Some(on_failure) = on_failure {
+            if task.timeout.is_none() && task.abort_on.is_empty() {
+                return Err("on_failure needs a timeout or abort_on".to_string());
+            }
+            task = task.on_failure(on_failure.into());
+        }
+        Ok(Task(task))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invalid_at(result: Result<Choreography, ChoreographyError>) -> (usize, String) {
+        match result {
+            Err(ChoreographyError::Invalid { line, message, .. }) => (line, message),
+            Err(e) => panic!("unexpected error {}", e),
+            Ok(_) => panic!("should not parse"),
+        }
+    }
+
+    #[test]
+    fn ron() {
+        let text = r#"(
+            groups: {"pair": [[0, 0], [1, 0]]},
+            tasks: [
+                (task: "get_close"),
+                (task: "move", targets: [[-100.0, 50.0], [100.0, -50.0]], distance: 10.0),
+                (task: "move_shift", to: [80.0, 0.0], distance: 15.0, group: "pair"),
+                (task: "spin", duration: 1000, power: 0.3),
+                (task: "target_angle", angle: 90.0, tolerance: 20.0),
+                (task: "sync", name: "end"),
+                (task: "wait_button", cube: 1),
+            ],
+        )"#;
+        let tasks = parse(text, Format::Ron).unwrap().tasks;
+        assert_eq!(tasks.len(), 7);
+        assert!(matches!(tasks[0].what, TaskType::GetClose { distance } if distance == DIST_CLOSE));
+        assert!(
+            matches!(&tasks[1].what, TaskType::PairMovement { targets, .. } if targets[1] == vec2(100.0, -50.0))
+        );
+        assert_eq!(tasks[2].group, "pair");
+        assert!(matches!(
+            tasks[3].what,
+            TaskType::Spin { duration: 1000, .. }
+        ));
+        assert!(
+            matches!(tasks[4].what, TaskType::TargetAngle { angle, .. } if (angle - PI / 2.0).abs() < 1e-6)
+        );
+        assert_eq!(tasks[5].sync_name(), Some("end"));
+        assert!(matches!(
+            tasks[6].what,
+            TaskType::WaitButton { cube: Some(1) }
+        ));
+    }
+
+    #[test]
+    fn json() {
+        let text = r#"{
+            "tasks": [
+                {"task": "wiggle", "duration": 500, "power": 0.5},
+                {"task": "target_angles", "angles": [0, 180], "tolerance": 20}
+            ]
+        }"#;
+        let tasks = parse(text, Format::Json).unwrap().tasks;
+        assert!(matches!(tasks[0].what, TaskType::Wiggle { .. }));
+        assert!(
+            matches!(&tasks[1].what, TaskType::TargetAngles { angles, .. } if angles.len() == 2)
+        );
+    }
+
+    #[test]
+    fn toml() {
+        let text = r#"
+            [groups]
+            other = [[1, 0]]
+
+            [[tasks]]
+            task = "single_move"
+            to = [10.0, 20.0]
+            distance = 5.0
+
+            [[tasks]]
+            task = "wait"
+            duration = 200
+            group = "other"
+        "#;
+        let tasks = parse(text, Format::Toml).unwrap().tasks;
+        assert!(
+            matches!(tasks[0].what, TaskType::SingleMovement { target, .. } if target == vec2(10.0, 20.0))
+        );
+        assert_eq!(tasks[1].group, "other");
+    }
+
+    #[test]
+    fn groups_come_with_the_file() {
+        let text = r#"{
+            "groups": {"left": [[0, 0]], "right": [[1, 0], [1, 1]]},
+            "tasks": [
+                {"task": "wait", "duration": 10, "group": "left"},
+                {"task": "sequence", "tasks": [{"task": "wait", "duration": 10}]}
+            ]
+        }"#;
+        let file = parse(text, Format::Json).unwrap();
+        assert_eq!(file.groups.get("left"), Some(&[CubeId::new(0, 0)][..]));
+        assert_eq!(
+            file.groups.get("right"),
+            Some(&[CubeId::new(1, 0), CubeId::new(1, 1)][..])
+        );
+        //the default group is the app's
+        assert!(file.groups.get(DEFAULT_GROUP).is_none());
+
+        //down the composites and the recoveries too
+        for task in [
+            r#"{"task": "wait", "duration": 10, "group": "middle"}"#,
+            r#"{"task": "sequence", "tasks": [{"task": "wait", "duration": 10, "group": "middle"}]}"#,
+            r#"{"task": "wait", "duration": 10, "timeout": 100,
+                "on_failure": {"recover": [{"task": "wait", "duration": 10, "group": "middle"}]}}"#,
+        ]
+        .iter()
+        {
+            let json = format!(r#"{{"groups": {{"left": [[0, 0]]}}, "tasks": [{}]}}"#, task);
+            let (_, message) = invalid_at(parse(&json, Format::Json));
+            assert!(message.contains("middle"), "{}", message);
+        }
+        let json = r#"{"groups": {"left": []}, "tasks": []}"#;
+        let (_, message) = invalid_at(parse(json, Format::Json));
+        assert!(message.contains("left"), "{}", message);
+        //a cube the group doesn't have, the task would wait for it forever
+        for task in [
+            r#"{"task": "wait_button", "cube": 1, "group": "left"}"#,
+            r#"{"task": "wait_card", "cube": 1, "group": "left"}"#,
+            r#"{"task": "loop_until", "tasks": [{"task": "wait", "duration": 10}],
+                "until": {"task": "wait_motion", "motion": "shake", "cube": 1, "group": "left"}}"#,
+        ]
+        .iter()
+        {
+            let json = format!(r#"{{"groups": {{"left": [[0, 0]]}}, "tasks": [{}]}}"#, task);
+            let (_, message) = invalid_at(parse(&json, Format::Json));
+            assert!(message.contains("task 1 needs 2 cubes"), "{}", message);
+        }
+        let json = r#"{"groups": {"left": [[0, 0]]},
+            "tasks": [{"task": "wait_button", "cube": 0, "group": "left"}]}"#;
+        assert!(parse(json, Format::Json).is_ok());
+        for cube in ["[0, -1]", "[0]", "[0, 1, 2]"].iter() {
+            let json = format!(r#"{{"groups": {{"left": [{}]}}, "tasks": []}}"#, cube);
+            let (_, message) = invalid_at(parse(&json, Format::Json));
+            assert!(message.contains("[host, cube]"), "{}", message);
+        }
+    }
+
+    #[test]
+    fn failure_policies() {
+        let text = r#"(
+            tasks: [
+                (task: "get_close", timeout: 5000),
+                (task: "get_close", timeout: 5000, on_failure: {"retry": 2}),
+                (task: "get_close", timeout: 5000, on_failure: "abort"),
+                (task: "get_close", timeout: 5000, on_failure: {"recover": [
+                    (task: "get_away"),
+                    (task: "wait", duration: 500),
+                ]}),
+            ],
+        )"#;
+        let tasks = parse(text, Format::Ron).unwrap().tasks;
+        assert_eq!(tasks[0].timeout, Some(5000));
+        assert!(matches!(tasks[0].on_failure, OnFailure::Skip));
+        assert!(matches!(tasks[1].on_failure, OnFailure::Retry(2)));
+        assert!(matches!(tasks[2].on_failure, OnFailure::AbortQueue));
+        assert!(matches!(&tasks[3].on_failure, OnFailure::Recover(r) if r.len() == 2));
+
+        let json = r#"{"tasks": [{"task": "spin", "duration": 10, "power": 1, "timeout": 20,
+            "on_failure": {"recover": [{"task": "wait", "duration": 10}]}}]}"#;
+        assert!(parse(json, Format::Json).is_ok());
+
+        //a policy without a timeout would never be used
+        let text = "(tasks: [\n(task: \"get_close\", on_failure: \"abort\")])";
+        let (line, message) = invalid_at(parse(text, Format::Ron));
+        assert_eq!(line, 2);
+        assert!(message.contains("timeout"), "{}", message);
+    }
+
+    #[test]
+    fn errors_point_at_the_task() {
+        let ron = "(\n tasks: [\n  (task: \"wait\", duration: 10),\n  (task: \"wait\", duration: 0),\n ],\n)";
+        let (line, message) = invalid_at(parse(ron, Format::Ron));
+        assert_eq!(line, 4);
+        assert!(message.contains("duration"), "{}", message);
+
+        let json =
+            "{\"tasks\": [\n {\"task\": \"wait\", \"duration\": 10},\n {\"task\": \"hop\"}\n]}";
+        let (line, message) = invalid_at(parse(json, Format::Json));
+        assert_eq!(line, 3);
+        assert!(message.contains("hop"), "{}", message);
+
+        let toml = "[[tasks]]\ntask = \"wait\"\nduration = 10\n\n[[tasks]]\ntask = \"spin\"\nduration = 10\n";
+        let (line, message) = invalid_at(parse(toml, Format::Toml));
+        assert!(line >= 5, "line {}", line);
+        assert!(message.contains("power"), "{}", message);
+
+        //a typo in a field, of the kind or of what every task takes
+        for field in ["durration", "time_out"].iter() {
+            let ron = format!("(tasks: [(task: \"wait\", duration: 10, {}: 20)])", field);
+            let (_, message) = invalid_at(parse(&ron, Format::Ron));
+            assert!(message.contains(field), "{}", message);
+        }
+    }
+
+    #[test]
+    fn composites() {
+        let text = r#"(
+            groups: {"a": [[0, 0]], "b": [[0, 1]]},
+            tasks: [
+                (task: "sequence", tasks: [(task: "get_close"), (task: "get_away")]),
+                (task: "parallel", join: "any", tasks: [
+                    (task: "spin", duration: 1000, power: 0.3, group: "a"),
+                    (task: "wait", duration: 500, group: "b"),
+                ]),
+                (task: "repeat", times: 3, tasks: [(task: "wiggle", duration: 500, power: 0.5)]),
+                (task: "repeat", tasks: [(task: "get_close"), (task: "get_away")]),
+                (task: "loop_until", until: (task: "wait", duration: 5000), tasks: [
+                    (task: "spin", duration: 1000, power: 0.3),
+                ]),
+            ],
+        )"#;
+        let tasks = parse(text, Format::Ron).unwrap().tasks;
+        assert!(matches!(&tasks[0].what, TaskType::Sequence(t) if t.len() == 2));
+        assert!(matches!(
+            &tasks[1].what,
+            TaskType::Parallel { tasks, join: Join::Any } if tasks[1].group == "b"
+        ));
+        assert!(matches!(
+            &tasks[2].what,
+            TaskType::Repeat { task, times: Some(3) } if matches!(task.what, TaskType::Wiggle { .. })
+        ));
+        assert!(matches!(
+            &tasks[3].what,
+            TaskType::Repeat { task, times: None } if matches!(task.what, TaskType::Sequence(_))
+        ));
+        assert!(
+            matches!(&tasks[4].what, TaskType::LoopUntil { until, .. } if matches!(until.what, TaskType::Wait { duration: 5000 }))
+        );
+
+        let json = r#"{"tasks": [{"task": "parallel", "tasks": []}]}"#;
+        let (_, message) = invalid_at(parse(json, Format::Json));
+        assert!(message.contains("tasks"), "{}", message);
+        let json =
+            r#"{"tasks": [{"task": "repeat", "times": 0, "tasks": [{"task": "get_close"}]}]}"#;
+        let (_, message) = invalid_at(parse(json, Format::Json));
+        assert!(message.contains("times"), "{}", message);
+    }
+
+    #[test]
+    fn cards() {
+        let text = r#"(
+            tasks: [
+                (task: "wait_card", card: 3, cube: 1),
+                (task: "branch_on_card", cases: [
+                    (card: 1, tasks: [(task: "spin", duration: 1000, power: 0.3)]),
+                    (card: 2, tasks: [(task: "get_close"), (task: "get_away")]),
+                ], otherwise: [(task: "wait", duration: 500)]),
+            ],
+        )"#;
+        let tasks = parse(text, Format::Ron).unwrap().tasks;
+        assert!(matches!(
+            tasks[0].what,
+            TaskType::WaitCard {
+                card: Some(3),
+                cube: Some(1)
+            }
+        ));
+        assert!(matches!(
+            &tasks[1].what,
+            TaskType::BranchOnCard { cases, otherwise: Some(_) }
+                if cases[0].0 == 1 && matches!(cases[1].1.what, TaskType::Sequence(_))
+        ));
+    }
+
+    #[test]
+    fn lights() {
+        let text = r#"(
+            tasks: [
+                (task: "set_light", color: [255, 0, 0]),
+                (task: "light_sequence", repeat: 2, steps: [
+                    (color: [0, 255, 0], duration: 200),
+                    (color: [0, 0, 255], duration: 300),
+                ]),
+            ],
+        )"#;
+        let tasks = parse(text, Format::Ron).unwrap().tasks;
+        assert!(matches!(
+            tasks[0].what,
+            TaskType::SetLight { light } if light == LightStep::new(255, 0, 0, 0)
+        ));
+        assert!(matches!(
+            &tasks[1].what,
+            TaskType::LightSequence { steps, repeat: Some(2) } if steps[1].duration == 300
+        ));
+
+        let json = r#"{"tasks": [{"task": "set_light", "color": [0, 0, 0], "duration": 3000}]}"#;
+        let (_, message) = invalid_at(parse(json, Format::Json));
+        assert!(message.contains("2550"), "{}", message);
+    }
+
+    #[test]
+    fn sounds() {
+        let text = r#"(
+            tasks: [
+                (task: "play_sound", effect: 3),
+                (task: "play_notes", melody: "C4 R:100 G4:500", volume: 100, repeat: 2),
+            ],
+        )"#;
+        let tasks = parse(text, Format::Ron).unwrap().tasks;
+        assert!(matches!(
+            tasks[0].what,
+            TaskType::PlaySound {
+                effect: 3,
+                volume: 255
+            }
+        ));
+        assert!(matches!(
+            &tasks[1].what,
+            TaskType::PlayNotes { notes, repeat: Some(2) }
+                if notes.len() == 3 && notes[0].duration == 250 && notes[2].volume == 100
+        ));
+
+        let json = r#"{"tasks": [{"task": "play_notes", "melody": "C4 H4"}]}"#;
+        let (_, message) = invalid_at(parse(json, Format::Json));
+        assert!(message.contains("H4"), "{}", message);
+        let json = r#"{"tasks": [{"task": "play_sound", "effect": 11}]}"#;
+        let (_, message) = invalid_at(parse(json, Format::Json));
+        assert!(message.contains("effect"), "{}", message);
+    }
+
+    #[test]
+    fn motions() {
+        let text = r#"(
+            tasks: [
+                (task: "move", targets: [[0.0, 0.0], [60.0, 0.0]], distance: 15.0,
+                    abort_on: ["collision", "tilted"], on_failure: "abort"),
+                (task: "wait_motion", motion: "double_tap", cube: 1),
+            ],
+        )"#;
+        let tasks = parse(text, Format::Ron).unwrap().tasks;
+        assert_eq!(
+            tasks[0].abort_on,
+            vec![MotionEvent::Collision, MotionEvent::Tilted]
+        );
+        assert!(matches!(tasks[0].on_failure, OnFailure::AbortQueue));
+        assert!(matches!(
+            tasks[1].what,
+            TaskType::WaitMotion {
+                motion: MotionEvent::DoubleTap,
+                cube: Some(1)
+            }
+        ));
+
+        let json = r#"{"tasks": [{"task": "get_close", "abort_on": ["bump"]}]}"#;
+        let (_, message) = invalid_at(parse(json, Format::Json));
+        assert!(message.contains("bump"), "{}", message);
+    }
+
+    #[test]
+    fn targets() {
+        let text = r#"(
+            tasks: [
+                (task: "move_to", targets: [(at: [-100.0, 0.0], angle: 90.0), (at: [100.0, 0.0])]),
+                (task: "follow_path", movement: "turn_first", max_speed: 50,
+                    speed_change: "accelerate_decelerate", cube_timeout: 20, paths: [
+                    [(at: [0.0, 0.0]), (at: [0.0, 100.0])],
+                ]),
+            ],
+        )"#;
+        let tasks = parse(text, Format::Ron).unwrap().tasks;
+        assert!(matches!(
+            &tasks[0].what,
+            TaskType::MoveTo { targets, config }
+                if targets[0].angle == Some(PI * 0.5)
+                    && targets[1] == Waypoint::new(100.0, 0.0, None)
+                    && *config == TargetConfig::default()
+        ));
+        assert!(matches!(
+            &tasks[1].what,
+            TaskType::FollowPath { paths, config }
+                if paths[0].len() == 2
+                    && *config == TargetConfig {
+                        movement: MovementType::TurnFirst,
+                        max_speed: 50,
+                        speed_change: SpeedChange::AccelerateDecelerate,
+                        timeout: 20,
+                    }
+        ));
+
+        let json =
+            r#"{"tasks": [{"task": "move_to", "targets": [{"at": [0, 0]}], "max_speed": 200}]}"#;
+        let (_, message) = invalid_at(parse(json, Format::Json));
+        assert!(message.contains("max_speed"), "{}", message);
+    }
+
+    //same kind of tasks with the same parameters, down the composites
+    fn assert_same(from_file: &[ToioTask], from_code: &[ToioTask]) {
+        assert_eq!(from_file.len(), from_code.len());
+        for (a, b) in from_file.iter().zip(from_code.iter()) {
+            match (&a.what, &b.what) {
+                (TaskType::Sequence(ta), TaskType::Sequence(tb)) => assert_same(ta, tb),
+                (
+                    TaskType::Repeat {
+                        task: ta,
+                        times: na,
+                    },
+                    TaskType::Repeat {
+                        task: tb,
+                        times: nb,
+                    },
+                ) => {
+                    assert_eq!(na, nb);
+                    assert_same(&[(**ta).clone()], &[(**tb).clone()]);
+                }
+                (
+                    TaskType::PairMovementShift {
+                        target: ta,
+                        distance: da,
+                    },
+                    TaskType::PairMovementShift {
+                        target: tb,
+                        distance: db,
+                    },
+                ) => {
+                    assert!((*ta - *tb).magnitude() < 0.01);
+                    assert_eq!(da, db);
+                }
+                (TaskType::GetClose { distance: da }, TaskType::GetClose { distance: db })
+                | (TaskType::GetAway { distance: da }, TaskType::GetAway { distance: db }) => {
+                    assert_eq!(da, db)
+                }
+                _ => panic!("different tasks"),
+            }
+        }
+    }
+
+    #[test]
+    fn hexagon_file_matches_the_code() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("choreographies/hexagon.ron");
+        assert_same(&load(path).unwrap().tasks, &hexagon());
+    }
+
+    #[test]
+    fn watcher_reloads_changed_files() {
+        let path = std::env::temp_dir().join(format!("toio-watch-{}.ron", std::process::id()));
+        fs::write(&path, "(tasks: [(task: \"wait\", duration: 10)])").unwrap();
+        let mut watcher = ChoreographyWatcher::new(&path);
+        assert!(watcher.poll().is_none());
+
+        //some file systems only keep seconds, move the date by hand
+        let bump = |path: &Path, secs| {
+            let file = fs::File::options().write(true).open(path).unwrap();
+            let date = SystemTime::now() + std::time::Duration::from_secs(secs);
+            file.set_modified(date).unwrap();
+        };
+        fs::write(
+            &path,
+            "(tasks: [(task: \"wait\", duration: 10), (task: \"sync\", name: \"a\")])",
+        )
+        .unwrap();
+        bump(&path, 10);
+        assert_eq!(watcher.poll().unwrap().unwrap().tasks.len(), 2);
+        assert!(watcher.poll().is_none());
+
+        fs::write(&path, "(tasks: [(task: \"jump\")])").unwrap();
+        bump(&path, 20);
+        assert!(matches!(
+            watcher.poll(),
+            Some(Err(ChoreographyError::Invalid { line: 1, .. }))
+        ));
+        fs::remove_file(&path).unwrap();
+        assert!(watcher.poll().is_none());
+    }
+
+    #[test]
+    fn unknown_extension() {
+        assert!(matches!(
+            load("dance.txt"),
+            Err(ChoreographyError::UnknownFormat(_))
+        ));
+    }
+}
@@ -0,0 +1,180 @@
+use nannou::prelude::*;
+
+pub fn aim_angle(_x: f32, _y: f32, angle: f32, target_angle: f32) -> [i32; 2] {
+    //find the angle
+    let mut angle_to_target = target_angle - angle;
+    while angle_to_target > PI {
+        angle_to_target -= TAU;
+    }
+    while angle_to_target < -PI {
+        angle_to_target += TAU;
+    }
+
+    let d = (angle_to_target.abs() / PI * 90.0).floor() as i32 + 2;
+    if angle_to_target > 0.0 {
+        [-d, d]
+    } else {
+        [d, -d]
+    }
+}
+
+pub fn aim_back(x: f32, y: f32, angle: f32, tx: f32, ty: f32) -> [i32; 2] {
+    //find the angle
+    let mut angle_to_target = (ty - y).atan2(tx - x) - (angle);
+    while angle_to_target > PI {
+        angle_to_target -= TAU;
+    }
+    while angle_to_target < -PI {
+        angle_to_target += TAU;
+    }
+    let d = ((tx - x) * (tx - x) + (ty - y) * (ty - y)).sqrt();
+    if d > 13.0 {
+        if angle_to_target.abs() > PI * 0.2 {
+            if angle_to_target > 0.0 {
+                [-20, 20]
+            } else {
+                [20, -20]
+            }
+        } else {
+            let ds = 20 - (20.0 * angle_to_target.abs() / (PI * 0.2)).floor() as i32;
+            if angle_to_target < 0.0 {
+                [-ds, -20]
+            } else {
+                [-20, -ds]
+            }
+        }
+    } else {
+        [0, 0]
+    }
+}
+
+pub fn aimany(x: f32, y: f32, angle: f32, tx: f32, ty: f32) -> [i32; 2] {
+    //find the angle
+    let mut angle_to_target = (ty - y).atan2(tx - x) - angle;
+    while angle_to_target > PI {
+        angle_to_target -= TAU;
+    }
+    while angle_to_target < -PI {
+        angle_to_target += TAU;
+    }
+    let d = ((tx - x) * (tx - x) + (ty - y) * (ty - y)).sqrt();
+    if d > 10.0 {
+        if angle_to_target.abs() > PI * 0.5 {
+            if angle_to_target.abs() < PI * 0.8 {
+                if angle_to_target > 0.0 {
+                    [20, -20]
+                } else {
+                    [-20, 20]
+                }
+            } else {
+                let ds = 20 - (20.0 * (PI - angle_to_target.abs()) / (PI * 0.2)).floor() as i32;
+                if angle_to_target > 0.0 {
+                    [-ds, -20]
+                } else {
+                    [-20, -ds]
+                }
+            }
+        } else {
+            if angle_to_target.abs() > PI * 0.2 {
+                if angle_to_target > 0.0 {
+                    [-20, 20]
+                } else {
+                    [20, -20]
+                }
+            } else {
+                let ds = 20 - (20.0 * angle_to_target.abs() / (PI * 0.2)).floor() as i32;
+                if angle_to_target > 0.0 {
+                    [ds, 20]
+                } else {
+                    [20, ds]
+                }
+            }
+        }
+    } else {
+        [0, 0]
+    }
+}
+
+pub fn facetarget(x: f32, y: f32, angle: f32, tx: f32, ty: f32) -> [i32; 2] {
+    //find the angle
+    let mut angle_to_target = (ty - y).atan2(tx - x) - angle;
+    while angle_to_target > PI {
+        angle_to_target -= TAU;
+    }
+    while angle_to_target < -PI {
+        angle_to_target += TAU;
+    }
+
+    if angle_to_target.abs() > PI * 0.15 {
+        if angle_to_target > 0.0 {
+            [-20, 20]
+        } else {
+            [20, -20]
+        }
+    } else {
+        [0, 0]
+    }
+}
+
+pub fn aimstable(x: f32, y: f32, angle: f32, tx: f32, ty: f32) -> [i32; 2] {
+    let scale = 20;
+    //find the angle
+    let mut angle_to_target = (ty - y).atan2(tx - x) - angle;
+    while angle_to_target > PI {
+        angle_to_target -= TAU;
+    }
+    while angle_to_target < -PI {
+        angle_to_target += TAU;
+    }
+    let d = ((tx - x) * (tx - x) + (ty - y) * (ty - y)).sqrt();
+    if d > 15.0 {
+        if angle_to_target.abs() > PI * 0.2 {
+            if angle_to_target > 0.0 {
+                [-20, 20]
+            } else {
+                [20, -20]
+            }
+        } else {
+            let ds = scale - (scale as f32 * angle_to_target.abs() / (PI * 0.2)).floor() as i32;
+            if angle_to_target > 0.0 {
+                [ds, scale]
+            } else {
+                [scale, ds]
+            }
+        }
+    } else {
+        [0, 0]
+    }
+}
+
+pub fn aim(x: f32, y: f32, angle: f32, tx: f32, ty: f32) -> [i32; 2] {
+    let d = ((tx - x) * (tx - x) + (ty - y) * (ty - y)).sqrt() * 0.5;
+    let scale = 10 + d.min(50.0).floor() as i32;
+    //find the angle
+    let mut angle_to_target = (ty - y).atan2(tx - x) - angle;
+    while angle_to_target > PI {
+        angle_to_target -= TAU;
+    }
+    while angle_to_target < -PI {
+        angle_to_target += TAU;
+    }
+    let d = ((tx - x) * (tx - x) + (ty - y) * (ty - y)).sqrt();
+    if d > 15.0 {
+        if angle_to_target.abs() > PI * 0.2 {
+            if angle_to_target > 0.0 {
+                [-20, 20]
+            } else {
+                [20, -20]
+            }
+        } else {
+            let ds = scale - (scale as f32 * angle_to_target.abs() / (PI * 0.2)).floor() as i32;
+            if angle_to_target > 0.0 {
+                [ds, scale]
+            } else {
+                [scale, ds]
+            }
+        }
+    } else {
+        [0, 0]
+    }
+}
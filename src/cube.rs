@@ -0,0 +1,660 @@
+use nannou::prelude::*;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+//center of the mat, in mat coordinates
+pub const CENTER_X: i32 = 250;
+pub const CENTER_Y: i32 = 250;
+
+//x, y, angle in radians
+pub type Pose = (f32, f32, f32);
+
+//a point centered on the mat with y up, back in mat coordinates
+pub fn to_mat(p: Vector2) -> (i32, i32) {
+    (p.x.round() as i32 + CENTER_X, CENTER_Y - p.y.round() as i32)
+}
+
+/// A cube is identified by the bridge it is connected to and its id on that bridge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CubeId {
+    pub host: i32,
+    pub cube: i32,
+}
+
+impl CubeId {
+    pub fn new(host: i32, cube: i32) -> Self {
+        CubeId { host, cube }
+    }
+}
+
+impl fmt::Display for CubeId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.host, self.cube)
+    }
+}
+
+//host:cube, the way it's displayed
+impl FromStr for CubeId {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || format!("bad cube \"{}\", expected host:cube (e.g. 1:0)", s);
+        let mut parts = s.splitn(2, ':');
+        let host: i32 = parts
+            .next()
+            .unwrap_or("")
+            .trim()
+            .parse()
+            .map_err(|_| err())?;
+        let cube: i32 = parts
+            .next()
+            .ok_or_else(err)?
+            .trim()
+            .parse()
+            .map_err(|_| err())?;
+        if host < 0 || cube < 0 {
+            return Err(err());
+        }
+        Ok(CubeId::new(host, cube))
+    }
+}
+
+/// How a cube is doing, from how long ago we last heard of it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Liveness {
+    Live,
+    //no position for a little while, lifted or hidden from the mat
+    Stale,
+    //gone for long enough that we can't count on it
+    Lost,
+    //back after being stale or lost, live once it stays for a bit
+    Reconnected,
+}
+
+impl Liveness {
+    //can tasks use this cube?
+    pub fn is_up(self) -> bool {
+        matches!(self, Liveness::Live | Liveness::Reconnected)
+    }
+}
+
+/// When cubes go stale and lost, in ms without a position.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LivenessConfig {
+    pub stale_after: u64,
+    pub lost_after: u64,
+    //how long a reconnected cube has to keep reporting to be live again
+    pub settle: u64,
+}
+
+impl Default for LivenessConfig {
+    fn default() -> Self {
+        LivenessConfig {
+            stale_after: 500,
+            lost_after: 5000,
+            settle: 1000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LivenessEvent {
+    pub id: CubeId,
+    pub from: Liveness,
+    pub to: Liveness,
+}
+
+/// A Standard ID card or sticker a cube read.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Card {
+    pub value: u32,
+    pub angle: i32,
+    //when it was read
+    pub seen: u64,
+}
+
+/// How a move to a target ended, as the cube tells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetResult {
+    Reached,
+    //not there before the timeout of the command
+    Timeout,
+    //it couldn't read the mat anymore
+    OffMat,
+    InvalidParameters,
+    //e.g. the cube was turned off its wheels
+    InvalidState,
+    //another motor command came in the meantime
+    Overridden,
+    NotSupported,
+    Failed,
+}
+
+impl TargetResult {
+    //the numbers the cube uses, anything unknown is Failed
+    pub fn from_id(id: i32) -> Self {
+        match id {
+            0 => TargetResult::Reached,
+            1 => TargetResult::Timeout,
+            2 => TargetResult::OffMat,
+            3 => TargetResult::InvalidParameters,
+            4 => TargetResult::InvalidState,
+            5 => TargetResult::Overridden,
+            6 => TargetResult::NotSupported,
+            _ => TargetResult::Failed,
+        }
+    }
+    pub fn id(self) -> i32 {
+        self as i32
+    }
+}
+
+impl fmt::Display for TargetResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let what = match self {
+            TargetResult::Reached => "reached",
+            TargetResult::Timeout => "timed out",
+            TargetResult::OffMat => "off the mat",
+            TargetResult::InvalidParameters => "invalid parameters",
+            TargetResult::InvalidState => "not in a state to move",
+            TargetResult::Overridden => "overridden",
+            TargetResult::NotSupported => "not supported",
+            TargetResult::Failed => "failed",
+        };
+        write!(f, "{}", what)
+    }
+}
+
+/// The answer of a cube to a target request.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TargetReport {
+    pub request: u8,
+    pub result: TargetResult,
+    pub at: u64,
+}
+
+//how long the motion events of a cube are kept
+pub const MOTION_KEEP: u64 = 2000;
+
+/// Which side of a cube faces up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Posture {
+    //the normal way, on its wheels
+    Upright,
+    UpsideDown,
+    BackUp,
+    FrontUp,
+    RightUp,
+    LeftUp,
+}
+
+impl Posture {
+    //the numbers the cube uses, 1 to 6
+    pub fn from_id(id: i32) -> Option<Self> {
+        match id {
+            1 => Some(Posture::Upright),
+            2 => Some(Posture::UpsideDown),
+            3 => Some(Posture::BackUp),
+            4 => Some(Posture::FrontUp),
+            5 => Some(Posture::RightUp),
+            6 => Some(Posture::LeftUp),
+            _ => None,
+        }
+    }
+}
+
+/// Something the motion sensor of a cube noticed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MotionEvent {
+    //back flat on something level
+    Flat,
+    //not level anymore
+    Tilted,
+    //bumped into something
+    Collision,
+    DoubleTap,
+    //turned on another side, see CubeData::posture
+    Posture,
+    //shaken, see CubeData::shake for how hard
+    Shake,
+}
+
+impl fmt::Display for MotionEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            MotionEvent::Flat => "flat",
+            MotionEvent::Tilted => "tilted",
+            MotionEvent::Collision => "collision",
+            MotionEvent::DoubleTap => "double tap",
+            MotionEvent::Posture => "posture",
+            MotionEvent::Shake => "shake",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Motion {
+    pub event: MotionEvent,
+    pub at: u64,
+}
+
+/// What the motion sensor of a cube reports, collisions and double taps only for the
+/// moment they happen.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MotionReport {
+    pub flat: bool,
+    pub collision: bool,
+    pub double_tap: bool,
+    pub posture: Posture,
+    //0 when still, up to 10
+    pub shake: u8,
+}
+
+impl MotionReport {
+    //as the bridge sends it, an unknown posture counts as upright
+    pub fn new(flat: bool, collision: bool, double_tap: bool, posture: i32, shake: i32) -> Self {
+        MotionReport {
+            flat,
+            collision,
+            double_tap,
+            posture: Posture::from_id(posture).unwrap_or(Posture::Upright),
+            shake: shake.clamp(0, 10) as u8,
+        }
+    }
+}
+
+pub struct CubeData {
+    pub x: i32,
+    pub y: i32,
+    pub angle: i32,
+    pub last: u64,
+    pub state: Liveness,
+    //when the cube got to that state
+    pub since: u64,
+    //false from a missed position until the next position, x and y are where it left
+    pub on_mat: bool,
+    //the last card the cube was put on
+    pub card: Option<Card>,
+    //what the motion sensor last said
+    pub flat: bool,
+    pub posture: Posture,
+    pub shake: u8,
+    //what it noticed in the last MOTION_KEEP ms, oldest first
+    pub motions: Vec<Motion>,
+    //in %, None until the cube tells
+    pub battery: Option<u8>,
+    //how its last move to a target ended
+    pub target: Option<TargetReport>,
+}
+
+impl CubeData {
+    fn new(x: i32, y: i32, angle: i32, on_mat: bool, now: u64) -> Self {
+        CubeData {
+            x,
+            y,
+            angle,
+            last: now,
+            state: Liveness::Live,
+            since: now,
+            on_mat,
+            card: None,
+            flat: true,
+            posture: Posture::Upright,
+            shake: 0,
+            motions: Vec::new(),
+            battery: None,
+            target: None,
+        }
+    }
+    //x, y centered on the mat with y up, angle in radians
+    pub fn pose(&self) -> Pose {
+        (
+            self.x as f32,
+            self.y as f32,
+            self.angle as f32 / 360.0 * TAU,
+        )
+    }
+}
+
+#[derive(Default)]
+pub struct CubeRegistry {
+    cubes: HashMap<CubeId, CubeData>,
+    pub liveness: LivenessConfig,
+    //state changes since the last check
+    events: Vec<LivenessEvent>,
+}
+
+impl CubeRegistry {
+    pub fn new() -> Self {
+        CubeRegistry {
+            cubes: HashMap::new(),
+            liveness: LivenessConfig::default(),
+            events: Vec::new(),
+        }
+    }
+    //update (or insert) a cube from a position in mat coordinates
+    pub fn update(&mut self, id: CubeId, mat_x: i32, mat_y: i32, mat_angle: i32, now: u64) {
+        let x = mat_x - CENTER_X;
+        let y = CENTER_Y - mat_y;
+        let angle = -mat_angle;
+        match self.cubes.get_mut(&id) {
+            Some(toio) => {
+                //the pose starts over from this position, wherever the cube was put back
+                toio.angle = angle;
+                toio.x = x;
+                toio.y = y;
+                toio.on_mat = true;
+                heard_from(&mut self.events, id, toio, now);
+            }
+            None => {
+                self.cubes.insert(id, CubeData::new(x, y, angle, true, now));
+            }
+        }
+    }
+    //move the cubes we didn't hear from along, returns every change since the last call
+    pub fn check(&mut self, now: u64) -> Vec<LivenessEvent> {
+        let config = self.liveness;
+        for toio in self.cubes.values_mut() {
+            toio.motions.retain(|m| now < m.at + MOTION_KEEP);
+        }
+        for (id, toio) in self.cubes.iter_mut() {
+            let silent = now.saturating_sub(toio.last);
+            let to = match toio.state {
+                Liveness::Live | Liveness::Reconnected if silent > config.stale_after => {
+                    Liveness::Stale
+                }
+                Liveness::Stale if silent > config.lost_after => Liveness::Lost,
+                Liveness::Reconnected if now >= toio.since + config.settle => Liveness::Live,
+                _ => continue,
+            };
+            self.events.push(LivenessEvent {
+                id: *id,
+                from: toio.state,
+                to,
+            });
+            toio.state = to;
+            toio.since = now;
+        }
+        std::mem::take(&mut self.events)
+    }
+    //the cube left the mat, its pose means nothing until it reads the mat again
+    pub fn position_missed(&mut self, id: CubeId) {
+        if let Some(toio) = self.cubes.get_mut(&id) {
+            toio.on_mat = false;
+        }
+    }
+    //the cube is on a card, it can't be on the mat at the same time
+    pub fn read_card(&mut self, id: CubeId, value: u32, angle: i32, now: u64) {
+        let card = Some(Card {
+            value,
+            angle,
+            seen: now,
+        });
+        let toio = self
+            .cubes
+            .entry(id)
+            .or_insert_with(|| CubeData::new(0, 0, 0, false, now));
+        toio.card = card;
+        toio.on_mat = false;
+        heard_from(&mut self.events, id, toio, now);
+    }
+    //what changed since the last report of the cube, nothing for cubes we don't know
+    pub fn motion(&mut self, id: CubeId, report: MotionReport, now: u64) -> Vec<MotionEvent> {
+        let toio = match self.cubes.get_mut(&id) {
+            Some(toio) => toio,
+            None => return Vec::new(),
+        };
+        let mut events = Vec::new();
+        if report.flat != toio.flat {
+            events.push(if report.flat {
+                MotionEvent::Flat
+            } else {
+                MotionEvent::Tilted
+            });
+        }
+        if report.collision {
+            events.push(MotionEvent::Collision);
+        }
+        if report.double_tap {
+            events.push(MotionEvent::DoubleTap);
+        }
+        if report.posture != toio.posture {
+            events.push(MotionEvent::Posture);
+        }
+        //shaking harder or again
+        if report.shake > toio.shake {
+            events.push(MotionEvent::Shake);
+        }
+        toio.flat = report.flat;
+        toio.posture = report.posture;
+        toio.shake = report.shake;
+        toio.motions
+            .extend(events.iter().map(|&event| Motion { event, at: now }));
+        events
+    }
+    //the battery level of a cube we know, in %
+    pub fn battery(&mut self, id: CubeId, level: i32) {
+        if let Some(toio) = self.cubes.get_mut(&id) {
+            toio.battery = Some(level.clamp(0, 100) as u8);
+        }
+    }
+    //a cube we know answered a target request
+    pub fn target_result(&mut self, id: CubeId, request: i32, result: i32, now: u64) {
+        if let Some(toio) = self.cubes.get_mut(&id) {
+            toio.target = Some(TargetReport {
+                request: request.clamp(0, 255) as u8,
+                result: TargetResult::from_id(result),
+                at: now,
+            });
+        }
+    }
+    //can tasks drive this cube? true when we don't know it, tasks wait for its poses anyway
+    pub fn usable(&self, id: &CubeId) -> bool {
+        self.cubes
+            .get(id)
+            .is_none_or(|c| c.state.is_up() && c.on_mat)
+    }
+    //Live when we don't know the cube, tasks wait for its poses anyway
+    pub fn state(&self, id: &CubeId) -> Liveness {
+        self.cubes.get(id).map_or(Liveness::Live, |c| c.state)
+    }
+    pub fn get(&self, id: &CubeId) -> Option<&CubeData> {
+        self.cubes.get(id)
+    }
+    //poses of the given cubes, in order, if we know all of them and they are on the mat
+    pub fn poses(&self, ids: &[CubeId]) -> Option<Vec<Pose>> {
+        ids.iter()
+            .map(|id| self.cubes.get(id).filter(|c| c.on_mat).map(|c| c.pose()))
+            .collect()
+    }
+    pub fn iter(&self) -> impl Iterator<Item = (&CubeId, &CubeData)> {
+        self.cubes.iter()
+    }
+    pub fn len(&self) -> usize {
+        self.cubes.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.cubes.is_empty()
+    }
+}
+
+//a stale or lost cube that talks again is back
+fn heard_from(events: &mut Vec<LivenessEvent>, id: CubeId, toio: &mut CubeData, now: u64) {
+    toio.last = now;
+    if !toio.state.is_up() {
+        events.push(LivenessEvent {
+            id,
+            from: toio.state,
+            to: Liveness::Reconnected,
+        });
+        toio.state = Liveness::Reconnected;
+        toio.since = now;
+    }
+}
+
+/// Named groups of cubes that tasks can address.
+#[derive(Default)]
+pub struct CubeGroups {
+    groups: HashMap<String, Vec<CubeId>>,
+}
+
+impl CubeGroups {
+    pub fn new() -> Self {
+        CubeGroups {
+            groups: HashMap::new(),
+        }
+    }
+    pub fn insert(&mut self, name: &str, cubes: Vec<CubeId>) {
+        self.groups.insert(name.to_string(), cubes);
+    }
+    pub fn get(&self, name: &str) -> Option<&[CubeId]> {
+        self.groups.get(name).map(|g| g.as_slice())
+    }
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Vec<CubeId>)> {
+        self.groups.iter()
+    }
+    //put new in place of old in every group, or take old out of them when None
+    pub fn swap(&mut self, old: CubeId, new: Option<CubeId>) {
+        for cubes in self.groups.values_mut() {
+            match new {
+                Some(new) => cubes
+                    .iter_mut()
+                    .filter(|id| **id == old)
+                    .for_each(|id| *id = new),
+                None => cubes.retain(|id| *id != old),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cube_ids_read_back_as_displayed() {
+        let id = CubeId::new(1, 3);
+        assert_eq!(id.to_string().parse(), Ok(id));
+        for bad in ["1", "1:", ":3", "a:3", "1:-3", ""].iter() {
+            assert!(bad.parse::<CubeId>().is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn cubes_go_stale_lost_and_come_back() {
+        let mut toio = CubeRegistry::new();
+        let id = CubeId::new(0, 3);
+        toio.update(id, 250, 250, 0, 0);
+        assert!(toio.check(500).is_empty());
+        let event = |from, to| vec![LivenessEvent { id, from, to }];
+        assert_eq!(toio.check(501), event(Liveness::Live, Liveness::Stale));
+        assert!(toio.check(1000).is_empty());
+        assert_eq!(toio.check(5001), event(Liveness::Stale, Liveness::Lost));
+
+        //put back somewhere else
+        toio.update(id, 100, 100, 90, 6000);
+        assert_eq!(toio.state(&id), Liveness::Reconnected);
+        assert_eq!(toio.get(&id).unwrap().pose().0, -150.0);
+        assert_eq!(
+            toio.check(6000),
+            event(Liveness::Lost, Liveness::Reconnected)
+        );
+        toio.update(id, 100, 100, 90, 6900);
+        assert_eq!(
+            toio.check(7000),
+            event(Liveness::Reconnected, Liveness::Live)
+        );
+
+        toio.liveness.stale_after = 50;
+        assert_eq!(toio.check(6951), event(Liveness::Live, Liveness::Stale));
+        assert_eq!(toio.state(&CubeId::new(1, 0)), Liveness::Live);
+    }
+
+    #[test]
+    fn cards_are_kept_per_cube() {
+        let mut toio = CubeRegistry::new();
+        let id = CubeId::new(1, 2);
+        //a cube we never saw on the mat
+        toio.read_card(id, 3670016, 90, 100);
+        let card = toio.get(&id).unwrap().card.unwrap();
+        assert_eq!((card.value, card.angle, card.seen), (3670016, 90, 100));
+        assert!(!toio.usable(&id));
+        toio.read_card(id, 3670017, 0, 200);
+        toio.update(id, 250, 250, 0, 300);
+        //still the last card, even back on the mat
+        assert_eq!(toio.get(&id).unwrap().card.unwrap().value, 3670017);
+        assert!(toio.usable(&id));
+    }
+
+    #[test]
+    fn motions_are_kept_for_a_while() {
+        let mut toio = CubeRegistry::new();
+        let id = CubeId::new(0, 0);
+        let still = MotionReport {
+            flat: true,
+            collision: false,
+            double_tap: false,
+            posture: Posture::Upright,
+            shake: 0,
+        };
+        assert!(toio.motion(id, still, 0).is_empty());
+        toio.update(id, 250, 250, 0, 0);
+        let bumped = MotionReport {
+            flat: false,
+            collision: true,
+            ..still
+        };
+        assert_eq!(
+            toio.motion(id, bumped, 100),
+            vec![MotionEvent::Tilted, MotionEvent::Collision]
+        );
+        let flipped = MotionReport {
+            posture: Posture::UpsideDown,
+            shake: 3,
+            ..still
+        };
+        assert_eq!(
+            toio.motion(id, flipped, 200),
+            vec![MotionEvent::Flat, MotionEvent::Posture, MotionEvent::Shake]
+        );
+        //calming down isn't shaking
+        let calmer = MotionReport {
+            shake: 1,
+            ..flipped
+        };
+        assert!(toio.motion(id, calmer, 300).is_empty());
+        assert_eq!(toio.get(&id).unwrap().posture, Posture::UpsideDown);
+
+        toio.check(2150);
+        let left: Vec<_> = toio
+            .get(&id)
+            .unwrap()
+            .motions
+            .iter()
+            .map(|m| m.at)
+            .collect();
+        assert_eq!(left, vec![200, 200, 200]);
+    }
+
+    #[test]
+    fn missed_positions_invalidate_the_pose() {
+        let mut toio = CubeRegistry::new();
+        let (a, b) = (CubeId::new(0, 0), CubeId::new(0, 1));
+        toio.update(a, 250, 250, 0, 0);
+        toio.update(b, 300, 250, 0, 0);
+        toio.position_missed(b);
+        assert!(toio.usable(&a));
+        assert!(!toio.usable(&b));
+        assert_eq!(toio.poses(&[a, b]), None);
+        assert!(toio.poses(&[a]).is_some());
+        //nothing happens for cubes we don't know
+        toio.position_missed(CubeId::new(3, 3));
+        assert!(toio.usable(&CubeId::new(3, 3)));
+
+        toio.update(b, 100, 250, 0, 10);
+        assert!(toio.usable(&b));
+        assert_eq!(toio.poses(&[b]), Some(vec![(-150.0, 0.0, 0.0)]));
+    }
+}
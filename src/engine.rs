@@ -0,0 +1,1411 @@
+//! Runs the task queues against the known cube poses.
+//!
+//! Every frame, `Engine::update` releases the sync points, moves finished queues to
+//! their next task and asks the current task of every queue for motor speeds. It
+//! doesn't send anything itself, so the same engine drives the bridges, the
+//! simulator or a test.
+//!
+//! Composite tasks (sequences, parallels, repeats and loops) are run as a tree: each
+//! of their branches is a queue of its own, run the same way as the top level ones.
+//! Syncs only make sense at the top level, inside a composite they're skipped.
+//!
+//! Cube buttons come in through `Engine::press`: the press is kept until the next
+//! update for the `WaitButton` tasks, and runs right away whatever was bound to the
+//! cube with `Engine::on_press`.
+//!
+//! Cubes whose battery runs low are dealt with by `Engine::battery`: a `LowBattery`
+//! event, then maybe a spare from `Engine::spares` takes their place in the groups, and
+//! maybe they drive to a parking spot in a queue of their own. A task whose group was
+//! left with fewer cubes than it needs fails with `FailureReason::ShortOfCubes`.
+//!
+//! `MoveTo` and `FollowPath` tasks let the cubes drive themselves: their target
+//! commands go out once, in `Tick::targets`, and the task is done when every cube
+//! answered that it got there (see `CubeRegistry::target_result`), or fails with what
+//! the cube said. A pause stops the cubes, they get their targets again on resume, and
+//! a target command that couldn't be sent goes out again after `Engine::unsent`.
+//!
+//! Light and sound tasks only ask for LED and speaker commands, in `Tick::lights` and
+//! `Tick::sounds`, and are done right away: put one in a `Parallel` with a move, or
+//! just before it, to light the cubes or play a tune while they move.
+
+use crate::cube::{to_mat, CubeGroups, CubeId, CubeRegistry, MotionEvent, Pose, TargetResult};
+use crate::protocol::{MatTarget, OutboundCommand};
+use crate::toiotask::DIST_AWAY;
+use crate::toiotask::{min_distance, release_syncs, Task, TaskQueue, TaskType, ToioTask};
+use crate::toiotask::{FailureAction, FailureReason, Join, ReloadMode, Waypoint};
+use nannou::prelude::*;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TaskEvent {
+    //the queue finished the task at this step of its list
+    Done {
+        queue: String,
+        step: usize,
+    },
+    //the task at this step timed out, and the queue did something about it
+    Failed {
+        queue: String,
+        step: usize,
+        reason: FailureReason,
+        action: FailureAction,
+    },
+    //a cube of the current task is stale or lost, the queue waits for it
+    Held {
+        queue: String,
+        cube: CubeId,
+    },
+    //every cube is back, the queue goes on
+    Released {
+        queue: String,
+    },
+    //the battery of the cube went below BatteryPolicy::low_below, spare took its place
+    //if the policy swaps it out and there was one
+    LowBattery {
+        cube: CubeId,
+        level: u8,
+        spare: Option<CubeId>,
+    },
+}
+
+/// What came out of a frame of the engine.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Tick {
+    pub motors: Vec<(CubeId, [i32; 2])>,
+    //LED commands, for the bridge of the cube
+    pub lights: Vec<(CubeId, OutboundCommand)>,
+    //sound effects and notes, same
+    pub sounds: Vec<(CubeId, OutboundCommand)>,
+    //moves the cubes do on their own, held back by an emergency stop like the motors
+    pub targets: Vec<(CubeId, OutboundCommand)>,
+    pub events: Vec<TaskEvent>,
+}
+
+/// What a button press does to a queue, or to anything for Custom.
+pub enum ButtonAction {
+    //resume the queue
+    Start(String),
+    //pause the queue, or resume it when it is paused
+    Pause(String),
+    //drop the current task of the queue
+    Skip(String),
+    //drop every task of the queue
+    Abort(String),
+    //called with the cube that was pressed, the queues and the time
+    Custom(Box<ButtonHandler>),
+}
+
+pub type ButtonHandler = dyn FnMut(CubeId, &mut Vec<TaskQueue>, u64);
+
+/// What happens to a cube whose battery runs low, on top of the `LowBattery` event.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BatteryAction {
+    //nothing more
+    Warn,
+    //out of every group, a spare takes its place if there is one, the tasks needing
+    //more cubes than what is left of their group fail
+    SwapOut,
+    //swapped out, then it drives there (app frame)
+    Park(Vector2),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BatteryPolicy {
+    //in %, a cube is low below that
+    pub low_below: u8,
+    pub action: BatteryAction,
+}
+
+impl Default for BatteryPolicy {
+    fn default() -> Self {
+        BatteryPolicy {
+            low_below: 20,
+            action: BatteryAction::Warn,
+        }
+    }
+}
+
+pub struct Engine {
+    pub queues: Vec<TaskQueue>,
+    pub groups: CubeGroups,
+    //when set, cubes closer than DIST_AWAY get apart before going on, until a GetClose
+    pub stay_away: bool,
+    //where each cube sits in its group, kept across tasks
+    shifts: HashMap<String, Vec<Vector2>>,
+    //what the button of each cube does
+    triggers: Vec<(CubeId, ButtonAction)>,
+    //presses since the last update
+    presses: Vec<CubeId>,
+    pub battery: BatteryPolicy,
+    //cubes waiting to take the place of the ones with a low battery
+    pub spares: Vec<CubeId>,
+    //cubes we already know are low, until they are charged again
+    low: Vec<CubeId>,
+    //id of the next target request
+    request: u8,
+}
+
+impl Engine {
+    pub fn new(groups: CubeGroups, queues: Vec<TaskQueue>) -> Self {
+        Engine {
+            queues,
+            groups,
+            stay_away: false,
+            shifts: HashMap::new(),
+            triggers: Vec::new(),
+            presses: Vec::new(),
+            battery: BatteryPolicy::default(),
+            spares: Vec::new(),
+            low: Vec::new(),
+            request: 0,
+        }
+    }
+    //every queue at once, see TaskQueue for one of them
+    pub fn pause(&mut self, now: u64) {
+        self.queues.iter_mut().for_each(|q| q.pause(now));
+    }
+    pub fn resume(&mut self, now: u64) {
+        self.queues.iter_mut().for_each(|q| q.resume(now));
+    }
+    //paused as soon as one queue is, held queues don't count
+    pub fn is_paused(&self) -> bool {
+        self.queues.iter().any(|q| q.is_paused())
+    }
+    //run the current tasks to their end, then pause again
+    pub fn step_through(&mut self, now: u64) {
+        self.queues.iter_mut().for_each(|q| q.step_through(now));
+    }
+    //drop the current tasks
+    pub fn skip(&mut self, now: u64) {
+        self.queues.iter_mut().for_each(|q| q.next(now));
+    }
+    //start every list over
+    pub fn restart(&mut self, now: u64) {
+        self.stay_away = false;
+        self.queues.iter_mut().for_each(|q| q.rewind(now));
+    }
+    //several actions can be bound to the same cube, they run in order
+    pub fn on_press(&mut self, cube: CubeId, action: ButtonAction) {
+        self.triggers.push((cube, action));
+    }
+    //the button of this cube was pressed
+    pub fn press(&mut self, cube: CubeId, now: u64) {
+        self.presses.push(cube);
+        for (id, action) in self.triggers.iter_mut() {
+            if *id != cube {
+                continue;
+            }
+            let name = match action {
+                ButtonAction::Custom(f) => {
+                    f(cube, &mut self.queues, now);
+                    continue;
+                }
+                ButtonAction::Start(name)
+                | ButtonAction::Pause(name)
+                | ButtonAction::Skip(name)
+                | ButtonAction::Abort(name) => name,
+            };
+            let queue = match self.queues.iter_mut().find(|q| q.name == *name) {
+                Some(queue) => queue,
+                None => continue,
+            };
+            match action {
+                ButtonAction::Start(_) => queue.resume(now),
+                ButtonAction::Pause(_) if queue.is_paused() => queue.resume(now),
+                ButtonAction::Pause(_) => queue.pause(now),
+                ButtonAction::Skip(_) => queue.next(now),
+                ButtonAction::Abort(_) => queue.clear(),
+                ButtonAction::Custom(_) => {}
+            }
+        }
+    }
+    //the target command for this cube was dropped (e.g. during an emergency stop), it
+    //goes out again next update
+    pub fn unsent(&mut self, id: CubeId) {
+        for queue in self.queues.iter_mut() {
+            if let Some(task) = queue.current_mut() {
+                task.unsent(id);
+            }
+        }
+    }
+    pub fn queue_mut(&mut self, name: &str) -> Option<&mut TaskQueue> {
+        self.queues.iter_mut().find(|q| q.name == name)
+    }
+    pub fn is_empty(&self) -> bool {
+        self.queues.iter().all(|q| q.is_empty())
+    }
+    pub fn update(&mut self, toio: &CubeRegistry, now: u64) -> Tick {
+        let mut tick = Tick::default();
+
+        //before the queues, so that a swapped out cube gets no more of their commands
+        self.check_batteries(toio, now, &mut tick);
+
+        //syncs first so that released queues start their next task right away
+        release_syncs(&mut self.queues, now);
+
+        let mut ctx = Context {
+            toio,
+            groups: &self.groups,
+            shifts: &mut self.shifts,
+            stay_away: &mut self.stay_away,
+            tick: &mut tick,
+            presses: &self.presses,
+            request: &mut self.request,
+            queue: String::new(),
+            step: 0,
+        };
+        for queue in self.queues.iter_mut() {
+            //only the queues using a missing cube wait for it
+            let missing = queue.current().and_then(|task| {
+                task.groups()
+                    .into_iter()
+                    .flat_map(|g| ctx.groups.get(g).unwrap_or(&[]))
+                    .find(|id| !toio.usable(id))
+                    .copied()
+            });
+            //apart from the pause, so that a release doesn't undo what the operator did
+            match (missing, queue.is_held()) {
+                (Some(cube), false) => {
+                    queue.hold(now);
+                    ctx.tick.events.push(TaskEvent::Held {
+                        queue: queue.name.clone(),
+                        cube,
+                    });
+                }
+                (None, true) => {
+                    queue.release(now);
+                    ctx.tick.events.push(TaskEvent::Released {
+                        queue: queue.name.clone(),
+                    });
+                }
+                _ => {}
+            }
+            if queue.is_paused() || queue.is_held() {
+                //hold the cubes still until we resume, but leave alone the ones off the mat
+                let groups = queue.current().map(|t| t.groups()).unwrap_or_default();
+                for group in groups {
+                    for id in ctx.groups.get(group).unwrap_or(&[]) {
+                        if toio.usable(id) {
+                            ctx.tick.motors.push((*id, [0, 0]));
+                        }
+                    }
+                }
+                continue;
+            }
+            ctx.queue = queue.name.clone();
+            run_queue(queue, now, false, &mut ctx);
+        }
+        self.presses.clear();
+        tick
+    }
+    //apply the battery policy once to every cube that went low
+    fn check_batteries(&mut self, toio: &CubeRegistry, now: u64, tick: &mut Tick) {
+        let threshold = self.battery.low_below;
+        let level = |id: &CubeId| toio.get(id).and_then(|c| c.battery);
+        //charged again, it would be warned about next time
+        self.low
+            .retain(|id| level(id).is_none_or(|l| l < threshold));
+        let mut went_low: Vec<(CubeId, u8)> = toio
+            .iter()
+            .filter_map(|(id, c)| Some((*id, c.battery?)))
+            .filter(|(id, l)| *l < threshold && !self.low.contains(id))
+            .collect();
+        went_low.sort();
+        for (cube, level) in went_low {
+            self.low.push(cube);
+            self.spares.retain(|id| *id != cube);
+            let spare = match self.battery.action {
+                BatteryAction::Warn => None,
+                BatteryAction::SwapOut | BatteryAction::Park(_) => self.swap_out(cube, toio),
+            };
+            if let BatteryAction::Park(spot) = self.battery.action {
+                let name = format!("park {}", cube);
+                self.groups.insert(&name, vec![cube]);
+                let park = ToioTask::new_single_move(spot.x, spot.y, 15.0).on(&name);
+                //low again, it drives there again from its own queue
+                match self.queue_mut(&name) {
+                    Some(queue) => queue.replace(vec![park], ReloadMode::Restart, now),
+                    None => self.queues.push(TaskQueue::new(&name, vec![park])),
+                }
+            }
+            tick.events
+                .push(TaskEvent::LowBattery { cube, level, spare });
+        }
+    }
+    //the first usable spare takes the place of the cube in every group, if there's one
+    fn swap_out(&mut self, cube: CubeId, toio: &CubeRegistry) -> Option<CubeId> {
+        let spare = self
+            .spares
+            .iter()
+            .position(|id| toio.usable(id) && toio.get(id).is_some())
+            .map(|i| self.spares.remove(i));
+        self.groups.swap(cube, spare);
+        spare
+    }
+}
+
+enum Status {
+    Running,
+    Done,
+    Failed(FailureReason),
+    //the cubes need to do this first
+    Interrupt(Box<ToioTask>),
+}
+
+//what a frame needs while going down the task tree
+struct Context<'a> {
+    toio: &'a CubeRegistry,
+    groups: &'a CubeGroups,
+    shifts: &'a mut HashMap<String, Vec<Vector2>>,
+    stay_away: &'a mut bool,
+    tick: &'a mut Tick,
+    presses: &'a [CubeId],
+    request: &'a mut u8,
+    //the top level queue and its step, for the events
+    queue: String,
+    step: usize,
+}
+
+impl Context<'_> {
+    fn group(&self, name: &str) -> Vec<CubeId> {
+        self.groups.get(name).unwrap_or(&[]).to_vec()
+    }
+    fn poses(&self, group: &[CubeId]) -> Option<Vec<Pose>> {
+        self.toio.poses(group).filter(|d| !d.is_empty())
+    }
+    //the latest card read since then by cube i of the group, any of them when None
+    fn card_since(&self, group: &[CubeId], cube: Option<usize>, since: u64) -> Option<u32> {
+        group
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| cube.is_none_or(|c| c == *i))
+            .filter_map(|(_, id)| self.toio.get(id)?.card)
+            //the one from the frame the task started in came before it
+            .filter(|card| card.seen > since)
+            .max_by_key(|card| card.seen)
+            .map(|card| card.value)
+    }
+    //the first of these motions cube i of the group noticed after since, any cube when None
+    fn motion_since(
+        &self,
+        group: &[CubeId],
+        cube: Option<usize>,
+        motions: &[MotionEvent],
+        since: u64,
+    ) -> Option<MotionEvent> {
+        group
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| cube.is_none_or(|c| c == *i))
+            .filter_map(|(_, id)| self.toio.get(id))
+            .flat_map(|toio| toio.motions.iter())
+            //the ones from the frame the task started in came before it
+            .filter(|m| m.at > since && motions.contains(&m.event))
+            .min_by_key(|m| m.at)
+            .map(|m| m.event)
+    }
+}
+
+//runs the current task of the queue, nested queues are the branches of a composite task
+//an aborted nested queue makes its composite fail
+fn run_queue(queue: &mut TaskQueue, now: u64, nested: bool, ctx: &mut Context) -> Status {
+    queue.start(now);
+    let step = queue.step();
+    if !nested {
+        ctx.step = step;
+    }
+    let task = match queue.current_mut() {
+        Some(task) => task,
+        None => return Status::Done,
+    };
+    match run(task, now, nested, ctx) {
+        Status::Done => {
+            if !nested {
+                ctx.tick.events.push(TaskEvent::Done {
+                    queue: ctx.queue.clone(),
+                    step,
+                });
+            }
+            //go to the next task and start it
+            queue.next(now);
+        }
+        Status::Failed(reason) => {
+            let action = queue.fail(now);
+            ctx.tick.events.push(TaskEvent::Failed {
+                queue: ctx.queue.clone(),
+                step: ctx.step,
+                reason,
+                action,
+            });
+            if nested && action == FailureAction::Aborted {
+                return Status::Failed(reason);
+            }
+        }
+        Status::Interrupt(task) => queue.interrupt(*task, now),
+        Status::Running => {}
+    }
+    Status::Running
+}
+
+fn run(task: &mut ToioTask, now: u64, nested: bool, ctx: &mut Context) -> Status {
+    let group = ctx.group(&task.group);
+    if let Some(reason) = short_of_cubes(task, &group) {
+        return Status::Failed(reason);
+    }
+    let data = ctx.poses(&group);
+    if task.timed_out(now) {
+        let timeout = task.timeout.unwrap_or(0);
+        let reason = match &data {
+            _ if task.is_composite() => FailureReason::Timeout(timeout),
+            Some(data) if data.len() >= task.cubes_needed() => FailureReason::Timeout(timeout),
+            _ => FailureReason::MissingCubes(timeout),
+        };
+        return Status::Failed(reason);
+    }
+    if !task.abort_on.is_empty() {
+        if let Some(motion) = ctx.motion_since(&group, None, &task.abort_on, task.start_time) {
+            return Status::Failed(FailureReason::Motion(motion));
+        }
+    }
+    if task.sync_name().is_some() {
+        //waiting for the other queues, only top level queues meet
+        return if nested {
+            Status::Done
+        } else {
+            Status::Running
+        };
+    }
+    if let Some(status) = waited(task, &group, ctx) {
+        return status;
+    }
+    match &task.what {
+        TaskType::SetLight { light } => {
+            for id in group.iter() {
+                let command = OutboundCommand::light(id.cube, *light);
+                ctx.tick.lights.push((*id, command));
+            }
+            return Status::Done;
+        }
+        TaskType::LightSequence { steps, repeat } => {
+            for id in group.iter() {
+                //0 is forever for the cube
+                let repeat = repeat.map_or(0, |n| n as i32);
+                let command = OutboundCommand::light_sequence(id.cube, steps.clone(), repeat);
+                ctx.tick.lights.push((*id, command));
+            }
+            return Status::Done;
+        }
+        TaskType::PlaySound { effect, volume } => {
+            for id in group.iter() {
+                let command =
+                    OutboundCommand::sound_effect(id.cube, *effect as i32, *volume as i32);
+                ctx.tick.sounds.push((*id, command));
+            }
+            return Status::Done;
+        }
+        TaskType::PlayNotes { notes, repeat } => {
+            for id in group.iter() {
+                let repeat = repeat.map_or(0, |n| n as i32);
+                let command = OutboundCommand::midi(id.cube, notes.clone(), repeat);
+                ctx.tick.sounds.push((*id, command));
+            }
+            return Status::Done;
+        }
+        _ => {}
+    }
+    if task.is_composite() {
+        return run_composite(task, now, ctx);
+    }
+    let data = match data {
+        Some(data) => data,
+        None => return Status::Running,
+    };
+    //the cubes drive themselves, each closure builds the command of cube i, if it has one
+    let requests = &mut task.requests;
+    match &task.what {
+        TaskType::MoveTo { targets, config } => {
+            return run_targets(
+                requests,
+                task.start_time,
+                &group,
+                ctx,
+                |cube, i, request| {
+                    let target = targets.get(i)?;
+                    Some(OutboundCommand::Target {
+                        cube,
+                        request,
+                        target: mat_target(target),
+                        config: *config,
+                    })
+                },
+            );
+        }
+        TaskType::FollowPath { paths, config } => {
+            return run_targets(
+                requests,
+                task.start_time,
+                &group,
+                ctx,
+                |cube, i, request| {
+                    let path = paths.get(i)?;
+                    Some(OutboundCommand::Targets {
+                        cube,
+                        request,
+                        targets: path.iter().map(mat_target).collect(),
+                        config: *config,
+                    })
+                },
+            );
+        }
+        _ => {}
+    }
+    if *ctx.stay_away
+        && min_distance(&data) < DIST_AWAY - 3.0
+        && !matches!(task.what, TaskType::GetAway { .. })
+    {
+        let away = ToioTask::new_get_away(DIST_AWAY - 3.0).on(&task.group);
+        return Status::Interrupt(Box::new(away));
+    }
+    if task.is_done(now, &data) {
+        return Status::Done;
+    }
+    let shifts = ctx.shifts.entry(task.group.clone()).or_default();
+    let speeds = task.step(now, &data, shifts);
+    for (id, what) in group.iter().zip(speeds) {
+        if let Some(what) = what {
+            ctx.tick.motors.push((*id, what));
+        }
+    }
+    if let TaskType::GetClose { .. } = task.what {
+        *ctx.stay_away = false;
+    }
+    Status::Running
+}
+
+//sends the targets if the cubes don't have them yet, done once they all got there
+fn run_targets<F>(
+    requests: &mut Vec<(CubeId, u8)>,
+    start_time: u64,
+    group: &[CubeId],
+    ctx: &mut Context,
+    command: F,
+) -> Status
+where
+    F: Fn(i32, usize, u8) -> Option<OutboundCommand>,
+{
+    if requests.is_empty() {
+        for (i, id) in group.iter().enumerate() {
+            let request = *ctx.request;
+            if let Some(command) = command(id.cube, i, request) {
+                *ctx.request = request.wrapping_add(1);
+                ctx.tick.targets.push((*id, command));
+                requests.push((*id, request));
+            }
+        }
+    }
+    let mut done = true;
+    for (id, request) in requests.iter() {
+        //the answer to this request, not to an older one
+        let report = ctx
+            .toio
+            .get(id)
+            .and_then(|c| c.target)
+            .filter(|r| r.request == *request && r.at >= start_time);
+        match report.map(|r| r.result) {
+            Some(TargetResult::Reached) => {}
+            Some(result) => return Status::Failed(FailureReason::Target(result)),
+            None => done = false,
+        }
+    }
+    if done {
+        Status::Done
+    } else {
+        Status::Running
+    }
+}
+
+//in mat coordinates, the angle in degrees clockwise like the positions
+fn mat_target(waypoint: &Waypoint) -> MatTarget {
+    let (x, y) = to_mat(waypoint.at);
+    MatTarget {
+        x,
+        y,
+        angle: waypoint
+            .angle
+            .map(|a| (-a.to_degrees()).round().rem_euclid(360.0) as i32),
+    }
+}
+
+//no cube will join the group on its own, say so instead of waiting forever
+fn short_of_cubes(task: &ToioTask, group: &[CubeId]) -> Option<FailureReason> {
+    if group.len() < task.cubes_needed() {
+        return Some(FailureReason::ShortOfCubes {
+            cubes: group.len(),
+            needed: task.cubes_needed(),
+        });
+    }
+    None
+}
+
+//the waits the engine settles, their is_done knows nothing of buttons, cards or motions
+fn waited(task: &ToioTask, group: &[CubeId], ctx: &Context) -> Option<Status> {
+    if let TaskType::WaitButton { cube } = task.what {
+        //no need to know where the cubes are for that
+        let pressed = group
+            .iter()
+            .enumerate()
+            .any(|(i, id)| cube.is_none_or(|c| c == i) && ctx.presses.contains(id));
+        return Some(if pressed {
+            Status::Done
+        } else {
+            Status::Running
+        });
+    }
+    if let TaskType::WaitCard { card, cube } = task.what {
+        let read = ctx.card_since(group, cube, task.start_time);
+        return Some(match read {
+            Some(value) if card.is_none_or(|c| c == value) => Status::Done,
+            _ => Status::Running,
+        });
+    }
+    if let TaskType::WaitMotion { motion, cube } = task.what {
+        return Some(
+            match ctx.motion_since(group, cube, &[motion], task.start_time) {
+                Some(_) => Status::Done,
+                None => Status::Running,
+            },
+        );
+    }
+    None
+}
+
+//runs every branch of a composite one frame
+fn run_composite(task: &mut ToioTask, now: u64, ctx: &mut Context) -> Status {
+    if let TaskType::BranchOnCard { .. } = task.what {
+        if task.children.is_empty() {
+            //waiting for a card we have a task for
+            let group = ctx.group(&task.group);
+            match ctx.card_since(&group, None, task.start_time) {
+                Some(card) if task.choose(card) => {}
+                _ => return Status::Running,
+            }
+        }
+    }
+    if let TaskType::LoopUntil { until, .. } = &task.what {
+        //until is checked on its own group
+        let group = ctx.group(&until.group);
+        if let Some(reason) = short_of_cubes(until, &group) {
+            return Status::Failed(reason);
+        }
+        let data = ctx.poses(&group).unwrap_or_default();
+        let done = match waited(until, &group, ctx) {
+            Some(status) => matches!(status, Status::Done),
+            None => until.is_done(now, &data),
+        };
+        if done {
+            return Status::Done;
+        }
+    }
+    if let TaskType::Parallel {
+        join: Join::Any, ..
+    } = task.what
+    {
+        if task.is_done(now, &[]) {
+            return Status::Done;
+        }
+    }
+    for branch in task.children.iter_mut() {
+        if branch.is_empty() {
+            continue;
+        }
+        if let Status::Failed(reason) = run_queue(branch, now, true, ctx) {
+            return Status::Failed(reason);
+        }
+    }
+    let looping = matches!(
+        task.what,
+        TaskType::Repeat { .. } | TaskType::LoopUntil { .. }
+    );
+    if looping && task.children.iter().all(|q| q.is_empty()) {
+        task.next_iteration();
+    }
+    match task.what {
+        //checked before the branches, it may depend on them
+        TaskType::LoopUntil { .. } => Status::Running,
+        _ if task.is_done(now, &[]) => Status::Done,
+        _ => Status::Running,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cube::MotionReport;
+    use crate::protocol::{Note, TargetConfig};
+    use crate::toiotask::{OnFailure, DEFAULT_GROUP};
+
+    fn two_cubes(tasks: Vec<ToioTask>) -> (Engine, CubeRegistry) {
+        let mut groups = CubeGroups::new();
+        groups.insert(DEFAULT_GROUP, vec![CubeId::new(0, 0), CubeId::new(0, 1)]);
+        let mut toio = CubeRegistry::new();
+        //side by side, both facing right
+        toio.update(CubeId::new(0, 0), 200, 250, 0, 0);
+        toio.update(CubeId::new(0, 1), 300, 250, 0, 0);
+        (
+            Engine::new(groups, vec![TaskQueue::new(DEFAULT_GROUP, tasks)]),
+            toio,
+        )
+    }
+
+    #[test]
+    fn every_task_type_moves_the_cubes() {
+        let tasks = vec![
+            ToioTask::new_pair_move(-100.0, 100.0, 100.0, 100.0, 5.0),
+            ToioTask::new_single_move(0.0, 100.0, 5.0),
+            ToioTask::new_pair_move_shift(0.0, 100.0, 5.0),
+            ToioTask::new_wiggle(1000, 0.5),
+            ToioTask::new_spin(1000, 0.5),
+            ToioTask::new_get_close(DIST_AWAY),
+            ToioTask::new_get_away(150.0),
+            ToioTask::new_target_angle(PI * 0.5, 0.1),
+            ToioTask::new_target_angles(PI * 0.5, -PI * 0.5, 0.1),
+        ];
+        for task in tasks {
+            let (mut engine, toio) = two_cubes(vec![task]);
+            let tick = engine.update(&toio, 100);
+            assert!(tick.events.is_empty());
+            assert!(!tick.motors.is_empty());
+            assert!(tick.motors.iter().any(|(_, s)| *s != [0, 0]));
+        }
+    }
+
+    #[test]
+    fn wait_holds_the_cubes() {
+        let (mut engine, toio) = two_cubes(vec![ToioTask::new_wait(100)]);
+        let tick = engine.update(&toio, 0);
+        assert_eq!(
+            tick.motors,
+            vec![(CubeId::new(0, 0), [0, 0]), (CubeId::new(0, 1), [0, 0])]
+        );
+        let tick = engine.update(&toio, 101);
+        assert_eq!(
+            tick.events,
+            vec![TaskEvent::Done {
+                queue: DEFAULT_GROUP.to_string(),
+                step: 0
+            }]
+        );
+        assert!(engine.is_empty());
+    }
+
+    #[test]
+    fn failures_are_reported() {
+        let stuck = ToioTask::new_pair_move(0.0, 200.0, 0.0, 300.0, 5.0).timeout(1000);
+        let (mut engine, toio) = two_cubes(vec![stuck, ToioTask::new_wait(100)]);
+        engine.update(&toio, 0);
+        assert!(engine.update(&toio, 1000).events.is_empty());
+        let tick = engine.update(&toio, 1001);
+        assert_eq!(
+            tick.events,
+            vec![TaskEvent::Failed {
+                queue: DEFAULT_GROUP.to_string(),
+                step: 0,
+                reason: FailureReason::Timeout(1000),
+                action: FailureAction::Skipped,
+            }]
+        );
+        assert_eq!(engine.queues[0].step(), 1);
+
+        //a group that never showed up times out too
+        let (mut lost, _) = two_cubes(vec![ToioTask::new_spin(5000, 0.5).timeout(10)]);
+        lost.update(&CubeRegistry::new(), 0);
+        let tick = lost.update(&CubeRegistry::new(), 11);
+        assert!(matches!(
+            tick.events[0],
+            TaskEvent::Failed {
+                reason: FailureReason::MissingCubes(10),
+                ..
+            }
+        ));
+    }
+
+    //runs frames of 10 ms until the queue is empty, returns when
+    fn run(engine: &mut Engine, toio: &CubeRegistry, limit: u64) -> Option<u64> {
+        (0..limit)
+            .step_by(10)
+            .find(|&now| engine.update(toio, now) == Tick::default() && engine.is_empty())
+    }
+
+    #[test]
+    fn repeats_go_through_their_task() {
+        let body = ToioTask::new_sequence(vec![ToioTask::new_wait(100), ToioTask::new_wait(50)]);
+        let (mut engine, toio) = two_cubes(vec![ToioTask::new_repeat(body, 3)]);
+        let end = run(&mut engine, &toio, 2000).unwrap();
+        //a frame to end each wait, and one for each sequence to notice
+        assert!((450..600).contains(&end), "{}", end);
+
+        let (mut engine, toio) = two_cubes(vec![ToioTask::new_forever(ToioTask::new_wait(10))]);
+        assert_eq!(run(&mut engine, &toio, 5000), None);
+    }
+
+    #[test]
+    fn parallels_join_their_branches() {
+        let branches = || vec![ToioTask::new_wait(100), ToioTask::new_wait(500)];
+        let (mut engine, toio) = two_cubes(vec![ToioTask::new_parallel(branches(), Join::Any)]);
+        let end = run(&mut engine, &toio, 2000).unwrap();
+        assert!((100..200).contains(&end), "{}", end);
+
+        let (mut engine, toio) = two_cubes(vec![ToioTask::new_parallel(branches(), Join::All)]);
+        let end = run(&mut engine, &toio, 2000).unwrap();
+        assert!((500..600).contains(&end), "{}", end);
+    }
+
+    #[test]
+    fn loops_stop_when_until_is_done() {
+        let spin = ToioTask::new_spin(30, 0.5);
+        let (mut engine, toio) = two_cubes(vec![ToioTask::new_loop_until(
+            spin,
+            ToioTask::new_wait(300),
+        )]);
+        let mut spins = 0;
+        for now in (0..1000).step_by(10) {
+            let tick = engine.update(&toio, now);
+            if tick.motors.iter().any(|(_, s)| *s != [0, 0]) {
+                spins += 1;
+            }
+        }
+        assert!(engine.is_empty());
+        //the spin keeps starting over until the wait is done
+        assert!(spins > 20, "{}", spins);
+        assert_eq!(run(&mut engine, &toio, 10), Some(0));
+    }
+
+    #[test]
+    fn aborted_branches_fail_their_composite() {
+        let stuck = ToioTask::new_pair_move(0.0, 200.0, 0.0, 300.0, 5.0)
+            .timeout(100)
+            .on_failure(OnFailure::AbortQueue);
+        let parallel = ToioTask::new_parallel(vec![stuck, ToioTask::new_wait(10_000)], Join::All)
+            .timeout(5000)
+            .on_failure(OnFailure::Recover(vec![ToioTask::new_wait(10)]));
+        let (mut engine, toio) = two_cubes(vec![parallel]);
+        let mut events = Vec::new();
+        for now in (0..300).step_by(10) {
+            events.extend(engine.update(&toio, now).events);
+        }
+        let actions: Vec<_> = events
+            .iter()
+            .filter_map(|e| match e {
+                TaskEvent::Failed { step, action, .. } => Some((*step, *action)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            actions,
+            vec![(0, FailureAction::Aborted), (0, FailureAction::Recovering)]
+        );
+        assert!(engine.is_empty());
+    }
+
+    #[test]
+    fn presses_release_button_waits() {
+        let tasks = vec![
+            ToioTask::new_wait_button(Some(1)),
+            ToioTask::new_wait_button(None),
+        ];
+        let (mut engine, toio) = two_cubes(tasks);
+        engine.update(&toio, 0);
+        //not the right cube
+        engine.press(CubeId::new(0, 0), 5);
+        assert!(engine.update(&toio, 10).events.is_empty());
+        engine.press(CubeId::new(0, 1), 15);
+        assert_eq!(engine.update(&toio, 20).events.len(), 1);
+        //presses don't carry over to the next task
+        assert!(engine.update(&toio, 30).events.is_empty());
+        engine.press(CubeId::new(0, 0), 35);
+        engine.update(&toio, 40);
+        assert!(engine.is_empty());
+    }
+
+    #[test]
+    fn presses_run_their_actions() {
+        let (mut engine, toio) = two_cubes(vec![
+            ToioTask::new_spin(1000, 0.5),
+            ToioTask::new_spin(1000, 0.5),
+            ToioTask::new_spin(1000, 0.5),
+        ]);
+        let (a, b) = (CubeId::new(0, 0), CubeId::new(0, 1));
+        engine.on_press(a, ButtonAction::Pause(DEFAULT_GROUP.to_string()));
+        engine.on_press(b, ButtonAction::Skip(DEFAULT_GROUP.to_string()));
+        engine.on_press(
+            b,
+            ButtonAction::Custom(Box::new(|id, queues, _| {
+                queues.push(TaskQueue::new(&id.to_string(), vec![]));
+            })),
+        );
+        engine.update(&toio, 0);
+        engine.press(a, 10);
+        //paused queues hold their cubes
+        let tick = engine.update(&toio, 20);
+        assert!(tick.motors.iter().all(|(_, s)| *s == [0, 0]));
+        engine.press(a, 30);
+        assert!(!engine.update(&toio, 40).motors.is_empty());
+        engine.press(b, 50);
+        assert_eq!(engine.queues[0].step(), 1);
+        assert_eq!(engine.queues.len(), 2);
+
+        engine.on_press(a, ButtonAction::Abort(DEFAULT_GROUP.to_string()));
+        engine.press(a, 60);
+        assert!(engine.queues[0].is_paused());
+        assert!(engine.is_empty());
+        engine.on_press(b, ButtonAction::Start(DEFAULT_GROUP.to_string()));
+        engine.press(b, 70);
+        assert!(!engine.queues[0].is_paused());
+    }
+
+    #[test]
+    fn pausing_freezes_the_tasks() {
+        let (mut engine, toio) = two_cubes(vec![
+            ToioTask::new_spin(100, 0.5),
+            ToioTask::new_spin(100, 0.5),
+        ]);
+        engine.update(&toio, 0);
+        engine.pause(50);
+        assert!(engine.is_paused());
+        for now in (60..1000).step_by(10) {
+            let tick = engine.update(&toio, now);
+            assert_eq!(tick.motors.len(), 2);
+            assert!(tick.motors.iter().all(|(_, s)| *s == [0, 0]));
+            assert!(tick.events.is_empty());
+        }
+        engine.resume(1000);
+        //50 ms of spin left
+        assert!(engine.update(&toio, 1040).events.is_empty());
+        assert_eq!(engine.update(&toio, 1060).events.len(), 1);
+
+        engine.pause(1100);
+        engine.step_through(1100);
+        //the second spin started at 1060
+        engine.update(&toio, 1150);
+        assert!(!engine.is_paused());
+        engine.update(&toio, 1170);
+        assert!(engine.is_paused());
+        assert!(engine.is_empty());
+
+        engine.restart(1200);
+        assert_eq!(engine.queues[0].tasks.len(), 2);
+        engine.skip(1200);
+        assert_eq!(engine.queues[0].step(), 1);
+    }
+
+    #[test]
+    fn missing_cubes_hold_their_queues_only() {
+        let mut groups = CubeGroups::new();
+        let (a, b) = (CubeId::new(0, 0), CubeId::new(0, 1));
+        groups.insert("a", vec![a]);
+        groups.insert("b", vec![b]);
+        let queues = vec![
+            TaskQueue::new("a", vec![ToioTask::new_spin(1000, 0.5).on("a")]),
+            TaskQueue::new("b", vec![ToioTask::new_spin(1000, 0.5).on("b")]),
+        ];
+        let mut engine = Engine::new(groups, queues);
+        let mut toio = CubeRegistry::new();
+        toio.update(a, 200, 250, 0, 0);
+        toio.update(b, 300, 250, 0, 0);
+        engine.update(&toio, 0);
+
+        //b is lifted
+        toio.update(a, 200, 250, 0, 600);
+        toio.check(600);
+        let tick = engine.update(&toio, 600);
+        assert_eq!(
+            tick.events,
+            vec![TaskEvent::Held {
+                queue: "b".to_string(),
+                cube: b
+            }]
+        );
+        //nothing for b, it wouldn't hear it anyway
+        assert!(tick.motors.iter().all(|(id, s)| *id == a && *s != [0, 0]));
+        assert!(engine.queues[1].is_held());
+        assert!(!engine.is_paused());
+
+        //and put back
+        toio.update(b, 300, 250, 0, 900);
+        toio.check(900);
+        let tick = engine.update(&toio, 900);
+        assert_eq!(
+            tick.events,
+            vec![TaskEvent::Released {
+                queue: "b".to_string()
+            }]
+        );
+        assert!(!engine.queues[1].is_held());
+        //the spin didn't run while b was away
+        assert_eq!(engine.update(&toio, 1001).events.len(), 1);
+        assert_eq!(engine.update(&toio, 1301).events.len(), 1);
+    }
+
+    #[test]
+    fn cubes_off_the_mat_get_no_commands() {
+        let (mut engine, mut toio) = two_cubes(vec![ToioTask::new_spin(1000, 0.5)]);
+        engine.update(&toio, 0);
+        toio.position_missed(CubeId::new(0, 1));
+        let tick = engine.update(&toio, 10);
+        assert!(matches!(tick.events[0], TaskEvent::Held { .. }));
+        assert_eq!(tick.motors, vec![(CubeId::new(0, 0), [0, 0])]);
+        //back on the mat
+        toio.update(CubeId::new(0, 1), 300, 250, 0, 20);
+        let tick = engine.update(&toio, 20);
+        assert!(matches!(tick.events[0], TaskEvent::Released { .. }));
+        assert_eq!(tick.motors.len(), 2);
+    }
+
+    #[test]
+    fn cards_pick_what_comes_next() {
+        let branch = ToioTask::new_branch_on_card(
+            vec![
+                (1, ToioTask::new_spin(100, 0.5)),
+                (2, ToioTask::new_wait(100)),
+            ],
+            None,
+        );
+        let tasks = vec![ToioTask::new_wait_card(Some(7), Some(1)), branch];
+        let (mut engine, mut toio) = two_cubes(tasks);
+        let a = CubeId::new(0, 0);
+        //read before the task started
+        toio.read_card(a, 7, 0, 0);
+        engine.update(&toio, 10);
+        toio.read_card(a, 7, 0, 20);
+        assert!(engine.update(&toio, 20).events.is_empty());
+        let b = CubeId::new(0, 1);
+        toio.read_card(b, 7, 0, 30);
+        assert_eq!(engine.update(&toio, 30).events.len(), 1);
+
+        //no task for card 3, it keeps waiting
+        toio.read_card(a, 3, 0, 40);
+        assert!(engine.update(&toio, 40).events.is_empty());
+        assert!(engine.queues[0].current().unwrap().children.is_empty());
+        toio.read_card(b, 2, 0, 50);
+        toio.update(b, 300, 250, 0, 50);
+        toio.update(a, 200, 250, 0, 50);
+        let tick = engine.update(&toio, 50);
+        assert!(tick.motors.iter().all(|(_, s)| *s == [0, 0]));
+        engine.update(&toio, 151);
+        engine.update(&toio, 152);
+        assert!(engine.is_empty());
+    }
+
+    #[test]
+    fn the_card_a_wait_ended_on_does_not_branch() {
+        let branch = ToioTask::new_branch_on_card(
+            vec![
+                (1, ToioTask::new_spin(100, 0.5)),
+                (2, ToioTask::new_wait(100)),
+            ],
+            None,
+        );
+        let tasks = vec![ToioTask::new_wait_card(None, None), branch];
+        let (mut engine, mut toio) = two_cubes(tasks);
+        let a = CubeId::new(0, 0);
+        engine.update(&toio, 0);
+        toio.read_card(a, 1, 0, 10);
+        assert_eq!(engine.update(&toio, 10).events.len(), 1);
+        //the branch starts in the frame the card was read
+        engine.update(&toio, 10);
+        engine.update(&toio, 20);
+        assert!(engine.queues[0].current().unwrap().children.is_empty());
+        toio.read_card(a, 2, 0, 30);
+        engine.update(&toio, 30);
+        assert!(matches!(
+            engine.queues[0].current().unwrap().children[0]
+                .current()
+                .unwrap()
+                .what,
+            TaskType::Wait { .. }
+        ));
+    }
+
+    #[test]
+    fn waits_on_a_cube_the_group_lacks_fail() {
+        let tasks = vec![
+            ToioTask::new_wait_button(Some(2)),
+            ToioTask::new_loop_until(
+                ToioTask::new_wait(10),
+                ToioTask::new_wait_card(None, Some(5)),
+            ),
+        ];
+        let (mut engine, toio) = two_cubes(tasks);
+        let short = |step, needed| TaskEvent::Failed {
+            queue: DEFAULT_GROUP.to_string(),
+            step,
+            reason: FailureReason::ShortOfCubes { cubes: 2, needed },
+            action: FailureAction::Skipped,
+        };
+        assert_eq!(engine.update(&toio, 0).events, vec![short(0, 3)]);
+        assert_eq!(engine.update(&toio, 10).events, vec![short(1, 6)]);
+        assert!(engine.is_empty());
+    }
+
+    #[test]
+    fn lights_go_along_with_moves() {
+        let tasks = vec![ToioTask::new_parallel(
+            vec![
+                ToioTask::new_set_light(255, 0, 0, 0),
+                ToioTask::new_spin(100, 0.5),
+            ],
+            Join::All,
+        )];
+        let (mut engine, mut toio) = two_cubes(tasks);
+        toio.update(CubeId::new(0, 0), 200, 250, 0, 0);
+        toio.update(CubeId::new(0, 1), 300, 250, 0, 0);
+        let tick = engine.update(&toio, 0);
+        assert_eq!(tick.lights.len(), 2);
+        assert!(tick.motors.iter().all(|(_, s)| *s == [50, -50]));
+        //sent once, the spin goes on
+        let tick = engine.update(&toio, 10);
+        assert!(tick.lights.is_empty());
+        assert_eq!(tick.motors.len(), 2);
+    }
+
+    #[test]
+    fn sounds_go_along_with_moves() {
+        let notes = vec![Note::new(60, 200, 255), Note::new(64, 200, 255)];
+        let tasks = vec![ToioTask::new_parallel(
+            vec![
+                ToioTask::new_play_sound(2, 255).on("b"),
+                ToioTask::new_play_notes(notes.clone(), Some(2)),
+                ToioTask::new_spin(100, 0.5),
+            ],
+            Join::All,
+        )];
+        let (mut engine, toio) = two_cubes(tasks);
+        engine.groups.insert("b", vec![CubeId::new(0, 1)]);
+        let tick = engine.update(&toio, 0);
+        assert!(tick.lights.is_empty());
+        assert_eq!(
+            tick.sounds,
+            vec![
+                (CubeId::new(0, 1), OutboundCommand::sound_effect(1, 2, 255)),
+                (
+                    CubeId::new(0, 0),
+                    OutboundCommand::midi(0, notes.clone(), 2)
+                ),
+                (CubeId::new(0, 1), OutboundCommand::midi(1, notes, 2)),
+            ]
+        );
+        assert!(tick.motors.iter().all(|(_, s)| *s == [50, -50]));
+        //sent once, the spin goes on
+        let tick = engine.update(&toio, 10);
+        assert!(tick.sounds.is_empty());
+        assert_eq!(tick.motors.len(), 2);
+    }
+
+    #[test]
+    fn motions_abort_tasks_or_end_waits() {
+        let (a, b) = (CubeId::new(0, 0), CubeId::new(0, 1));
+        let tasks = vec![
+            ToioTask::new_pair_move(-100.0, 0.0, 100.0, 0.0, 15.0)
+                .abort_on(MotionEvent::Collision)
+                .on_failure(OnFailure::Retry(1)),
+            ToioTask::new_wait_motion(MotionEvent::DoubleTap, Some(0)),
+        ];
+        let (mut engine, mut toio) = two_cubes(tasks);
+        let still = MotionReport::new(true, false, false, 1, 0);
+        let bump = MotionReport::new(true, true, false, 1, 0);
+        let tap = MotionReport::new(true, false, true, 1, 0);
+        toio.motion(b, bump, 0);
+        //from before the task started
+        assert!(engine.update(&toio, 0).events.is_empty());
+        toio.motion(b, still, 10);
+        toio.motion(b, bump, 20);
+        let failed = |action| TaskEvent::Failed {
+            queue: DEFAULT_GROUP.to_string(),
+            step: 0,
+            reason: FailureReason::Motion(MotionEvent::Collision),
+            action,
+        };
+        assert_eq!(
+            engine.update(&toio, 20).events,
+            vec![failed(FailureAction::Retried(1))]
+        );
+        assert!(engine.update(&toio, 30).events.is_empty());
+        toio.motion(a, bump, 40);
+        assert_eq!(
+            engine.update(&toio, 40).events,
+            vec![failed(FailureAction::Skipped)]
+        );
+
+        //only the first cube's taps count
+        toio.motion(b, tap, 50);
+        assert!(engine.update(&toio, 50).events.is_empty());
+        toio.motion(a, tap, 60);
+        assert_eq!(engine.update(&toio, 60).events.len(), 1);
+        assert!(engine.is_empty());
+    }
+
+    #[test]
+    fn dropped_targets_go_out_again() {
+        let targets = vec![
+            Waypoint::new(-100.0, 0.0, None),
+            Waypoint::new(100.0, 0.0, None),
+        ];
+        let tasks = vec![ToioTask::new_sequence(vec![ToioTask::new_move_to(
+            targets,
+            TargetConfig::default(),
+        )])];
+        let (mut engine, mut toio) = two_cubes(tasks);
+        assert_eq!(engine.update(&toio, 0).targets.len(), 2);
+        //the bridge of b didn't take it
+        engine.unsent(CubeId::new(0, 1));
+        let tick = engine.update(&toio, 10);
+        assert_eq!(tick.targets.len(), 2);
+        assert!(matches!(
+            tick.targets[1].1,
+            OutboundCommand::Target { request: 3, .. }
+        ));
+        toio.target_result(CubeId::new(0, 0), 2, 0, 20);
+        toio.target_result(CubeId::new(0, 1), 3, 0, 20);
+        assert!(engine.update(&toio, 20).targets.is_empty());
+        assert!(engine.is_empty());
+    }
+
+    #[test]
+    fn low_batteries_swap_cubes_out() {
+        let (a, b, c) = (CubeId::new(0, 0), CubeId::new(0, 1), CubeId::new(0, 2));
+        let tasks = vec![ToioTask::new_spin(10_000, 0.5)];
+        let (mut engine, mut toio) = two_cubes(tasks);
+        engine.battery.action = BatteryAction::Park(vec2(-200.0, -200.0));
+        engine.spares.push(c);
+        toio.update(c, 100, 100, 0, 0);
+        toio.battery(a, 80);
+        toio.battery(b, 19);
+        let low = |cube, level, spare| TaskEvent::LowBattery { cube, level, spare };
+        assert_eq!(engine.update(&toio, 0).events, vec![low(b, 19, Some(c))]);
+        assert_eq!(engine.groups.get(DEFAULT_GROUP), Some(&[a, c][..]));
+        //b drives to its spot on its own
+        let tick = engine.update(&toio, 10);
+        assert!(tick.events.is_empty());
+        assert!(tick.motors.iter().any(|(id, s)| *id == b && *s != [0, 0]));
+
+        //no spare left, a goes and the spin goes on with c
+        engine.battery.action = BatteryAction::SwapOut;
+        toio.battery(a, 5);
+        assert_eq!(engine.update(&toio, 20).events, vec![low(a, 5, None)]);
+        assert_eq!(engine.groups.get(DEFAULT_GROUP), Some(&[c][..]));
+        //charged, then low again
+        toio.battery(a, 100);
+        assert!(engine.update(&toio, 30).events.is_empty());
+        toio.battery(a, 10);
+        assert_eq!(engine.update(&toio, 40).events, vec![low(a, 10, None)]);
+
+        //b goes back to its spot, from the same queue
+        engine.battery.action = BatteryAction::Park(vec2(-200.0, -200.0));
+        engine.queue_mut("park 0:1").unwrap().clear();
+        toio.battery(b, 100);
+        engine.update(&toio, 50);
+        toio.battery(b, 10);
+        engine.update(&toio, 60);
+        let parks: Vec<_> = engine
+            .queues
+            .iter()
+            .filter(|q| q.name == "park 0:1")
+            .collect();
+        assert_eq!(parks.len(), 1);
+        assert!(!parks[0].is_empty());
+    }
+
+    #[test]
+    fn tasks_fail_when_their_group_is_short_of_cubes() {
+        let (a, b) = (CubeId::new(0, 0), CubeId::new(0, 1));
+        let tasks = vec![
+            ToioTask::new_pair_move(-100.0, 0.0, 100.0, 0.0, 15.0),
+            ToioTask::new_spin(100, 0.5),
+        ];
+        let (mut engine, mut toio) = two_cubes(tasks);
+        engine.battery.action = BatteryAction::SwapOut;
+        toio.battery(a, 80);
+        toio.battery(b, 10);
+        let tick = engine.update(&toio, 0);
+        assert_eq!(engine.groups.get(DEFAULT_GROUP), Some(&[a][..]));
+        assert_eq!(
+            tick.events[1],
+            TaskEvent::Failed {
+                queue: DEFAULT_GROUP.to_string(),
+                step: 0,
+                reason: FailureReason::ShortOfCubes {
+                    cubes: 1,
+                    needed: 2
+                },
+                action: FailureAction::Skipped,
+            }
+        );
+        //the spin needs no more than what is left
+        assert!(engine.queues[0]
+            .current()
+            .is_some_and(|t| matches!(t.what, TaskType::Spin { .. })));
+    }
+
+    #[test]
+    fn cubes_tell_when_they_reached_their_targets() {
+        let (a, b) = (CubeId::new(0, 0), CubeId::new(0, 1));
+        let targets = vec![
+            Waypoint::new(-100.0, 0.0, Some(PI * 0.5)),
+            Waypoint::new(100.0, 0.0, None),
+        ];
+        let tasks = vec![ToioTask::new_move_to(targets, TargetConfig::default())
+            .timeout(10_000)
+            .on_failure(OnFailure::Retry(1))];
+        let (mut engine, mut toio) = two_cubes(tasks);
+        let tick = engine.update(&toio, 0);
+        assert!(tick.motors.is_empty());
+        assert_eq!(tick.targets.len(), 2);
+        assert_eq!(
+            tick.targets[0].1,
+            OutboundCommand::Target {
+                cube: 0,
+                request: 0,
+                target: MatTarget {
+                    x: 150,
+                    y: 250,
+                    angle: Some(270)
+                },
+                config: TargetConfig::default(),
+            }
+        );
+        //sent once
+        assert!(engine.update(&toio, 10).targets.is_empty());
+        toio.target_result(a, 0, 0, 20);
+        assert!(engine.update(&toio, 20).events.is_empty());
+        //b couldn't, the retry asks again with new requests
+        toio.target_result(b, 1, 1, 30);
+        let tick = engine.update(&toio, 30);
+        assert!(matches!(
+            tick.events[..],
+            [TaskEvent::Failed {
+                reason: FailureReason::Target(TargetResult::Timeout),
+                action: FailureAction::Retried(1),
+                ..
+            }]
+        ));
+        let tick = engine.update(&toio, 40);
+        assert_eq!(tick.targets.len(), 2);
+        assert!(matches!(
+            tick.targets[1].1,
+            OutboundCommand::Target { request: 3, .. }
+        ));
+        //the answer to the old request doesn't count
+        assert!(engine.update(&toio, 50).events.is_empty());
+        toio.target_result(a, 2, 0, 60);
+        toio.target_result(b, 3, 0, 60);
+        assert_eq!(engine.update(&toio, 60).events.len(), 1);
+        assert!(engine.is_empty());
+    }
+
+    #[test]
+    fn unknown_cubes_get_no_commands() {
+        let (mut engine, _) = two_cubes(vec![ToioTask::new_spin(100, 0.5)]);
+        let tick = engine.update(&CubeRegistry::new(), 0);
+        assert_eq!(tick, Tick::default());
+    }
+}
@@ -0,0 +1,151 @@
+//! Differential-drive model of a toio cube.
+//!
+//! Poses are in the frame used by the rest of the crate: mat units centered on
+//! the mat, y up, angle in radians counter-clockwise.
+
+use crate::cube::Pose;
+use nannou::prelude::*;
+
+//distance between the two wheels
+pub const WHEEL_BASE_MM: f32 = 26.6;
+//the position mat has 411 units for 560mm
+pub const MM_PER_MAT_UNIT: f32 = 560.0 / 411.0;
+//a motor value of 1 is 4.3 rpm on a 12.5mm wheel
+pub const MM_S_PER_SPEED: f32 = 4.3 * PI * 12.5 / 60.0;
+//motor values below this don't move the wheel
+pub const DEAD_ZONE: i32 = 10;
+pub const MAX_SPEED: i32 = 115;
+
+pub fn wheel_base() -> f32 {
+    WHEEL_BASE_MM / MM_PER_MAT_UNIT
+}
+
+//speed of a wheel in mat units per second for a motor value
+pub fn wheel_speed(value: i32) -> f32 {
+    if value.abs() < DEAD_ZONE {
+        return 0.0;
+    }
+    let value = value.clamp(-MAX_SPEED, MAX_SPEED);
+    value as f32 * MM_S_PER_SPEED / MM_PER_MAT_UNIT
+}
+
+//where a cube ends up after dt seconds at constant motor values
+pub fn advance(pose: Pose, speeds: [i32; 2], dt: f32) -> Pose {
+    let (x, y, angle) = pose;
+    let vl = wheel_speed(speeds[0]);
+    let vr = wheel_speed(speeds[1]);
+    let v = (vl + vr) * 0.5;
+    let w = (vr - vl) / wheel_base();
+    if w.abs() < 1e-6 {
+        return (x + v * angle.cos() * dt, y + v * angle.sin() * dt, angle);
+    }
+    //follow the arc exactly so that big steps stay deterministic
+    let new_angle = angle + w * dt;
+    let r = v / w;
+    (
+        x + r * (new_angle.sin() - angle.sin()),
+        y - r * (new_angle.cos() - angle.cos()),
+        new_angle,
+    )
+}
+
+/// A cube following `/motor` commands: speeds hold for `duration` ms, 0 means until the next command.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KinematicCube {
+    pub pose: Pose,
+    pub speeds: [i32; 2],
+    pub until: Option<u64>,
+}
+
+impl KinematicCube {
+    pub fn new(pose: Pose) -> Self {
+        KinematicCube {
+            pose,
+            speeds: [0, 0],
+            until: None,
+        }
+    }
+    pub fn command(&mut self, speeds: [i32; 2], duration: u64, now: u64) {
+        self.speeds = speeds;
+        self.until = if duration == 0 {
+            None
+        } else {
+            Some(now + duration)
+        };
+    }
+    //move from now to now + dt ms, stopping when the command times out
+    pub fn step(&mut self, now: u64, dt: u64) {
+        let moving = match self.until {
+            Some(until) => until.saturating_sub(now).min(dt),
+            None => dt,
+        };
+        if moving > 0 {
+            self.pose = advance(self.pose, self.speeds, moving as f32 / 1000.0);
+        }
+        if let Some(until) = self.until {
+            if now + dt >= until {
+                self.speeds = [0, 0];
+                self.until = None;
+            }
+        }
+        let (x, y, angle) = self.pose;
+        self.pose = (x, y, (angle + PI).rem_euclid(TAU) - PI);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn straight_line() {
+        let (x, y, angle) = advance((0.0, 0.0, 0.0), [50, 50], 1.0);
+        assert!((x - wheel_speed(50)).abs() < 1e-3);
+        assert!(y.abs() < 1e-3);
+        assert!(angle.abs() < 1e-6);
+    }
+
+    #[test]
+    fn spin_in_place() {
+        let (x, y, angle) = advance((10.0, 20.0, 0.0), [-20, 20], 0.5);
+        assert!((x - 10.0).abs() < 1e-3);
+        assert!((y - 20.0).abs() < 1e-3);
+        //right wheel faster, counter-clockwise
+        assert!(angle > 0.0);
+        assert!((angle - 2.0 * wheel_speed(20) / wheel_base() * 0.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn dead_zone() {
+        assert_eq!(wheel_speed(9), 0.0);
+        assert_eq!(wheel_speed(-9), 0.0);
+        assert!(wheel_speed(10) > 0.0);
+        assert_eq!(advance((1.0, 2.0, 0.3), [5, -5], 10.0), (1.0, 2.0, 0.3));
+    }
+
+    #[test]
+    fn max_speed() {
+        assert_eq!(wheel_speed(200), wheel_speed(MAX_SPEED));
+    }
+
+    #[test]
+    fn command_times_out() {
+        let mut cube = KinematicCube::new((0.0, 0.0, 0.0));
+        cube.command([50, 50], 100, 0);
+        for t in 0..10 {
+            cube.step(t * 50, 50);
+        }
+        //only the first 100ms moved the cube
+        assert!((cube.pose.0 - wheel_speed(50) * 0.1).abs() < 1e-3);
+        assert_eq!(cube.speeds, [0, 0]);
+    }
+
+    #[test]
+    fn command_without_duration_keeps_going() {
+        let mut cube = KinematicCube::new((0.0, 0.0, 0.0));
+        cube.command([30, 30], 0, 0);
+        cube.step(0, 2000);
+        assert!((cube.pose.0 - wheel_speed(30) * 2.0).abs() < 1e-3);
+        assert_eq!(cube.speeds, [30, 30]);
+    }
+}
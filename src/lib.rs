@@ -0,0 +1,23 @@
+//! Controlling toio core cubes through the `toio-osc` bridge.
+//!
+//! * `protocol`: the OSC messages exchanged with the bridge
+//! * `bridge`: where to send commands when running several bridges
+//! * `cube`: the registry of known cubes and their last pose
+//! * `controllers`: steering functions returning `[left, right]` motor speeds
+//! * `kinematics`: where those motor speeds take a cube
+//! * `toiotask`: the task queue used to choreograph cubes
+//! * `engine`: runs the task queues and tells what to send to which cube
+//! * `choreography`: ready-made task lists and choreography files
+//! * `melody`: melodies written as text, turned into notes for the cubes
+//! * `sim`: simulated cubes speaking the bridge protocol, see the `toio-sim` binary
+
+pub mod bridge;
+pub mod choreography;
+pub mod controllers;
+pub mod cube;
+pub mod engine;
+pub mod kinematics;
+pub mod melody;
+pub mod protocol;
+pub mod sim;
+pub mod toiotask;
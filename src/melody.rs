@@ -0,0 +1,109 @@
+//! Melodies written as text, turned into the notes a cube plays.
+//!
+//! A melody is a list of notes separated by spaces. A note is its name (`C` to `B`,
+//! with `#` or `b` for sharp and flat) followed by its octave, `C4` being the middle C,
+//! or `R` for a rest. It lasts the default duration unless it says how many ms after a
+//! colon:
+//!
+//! ```text
+//! C4 E4 G4 C5:500 R:250 G4:125 C5:750
+//! ```
+
+use crate::protocol::{Note, MAX_NOTES, MAX_NOTE_MS, REST};
+
+//every note for duration ms unless it says otherwise, all as loud as volume
+pub fn melody(text: &str, duration: i32, volume: u8) -> Result<Vec<Note>, String> {
+    let notes = text
+        .split_whitespace()
+        .map(|token| note(token, duration, volume))
+        .collect::<Result<Vec<_>, _>>()?;
+    if notes.len() > MAX_NOTES {
+        return Err(format!(
+            "a cube plays at most {} notes, got {}",
+            MAX_NOTES,
+            notes.len()
+        ));
+    }
+    Ok(notes)
+}
+
+fn note(token: &str, duration: i32, volume: u8) -> Result<Note, String> {
+    let (pitch, duration) = match token.split_once(':') {
+        Some((pitch, ms)) => {
+            let ms = ms
+                .parse()
+                .map_err(|_| format!("{}: {} isn't a duration in ms", token, ms))?;
+            (pitch, ms)
+        }
+        None => (token, duration),
+    };
+    if duration <= 0 || duration > MAX_NOTE_MS {
+        return Err(format!(
+            "{}: a note lasts 1 to {} ms, got {}",
+            token, MAX_NOTE_MS, duration
+        ));
+    }
+    if pitch.eq_ignore_ascii_case("r") {
+        return Ok(Note::new(REST, duration, 0));
+    }
+    Ok(Note::new(midi_number(pitch)?, duration, volume))
+}
+
+//C4 is 60, A4 is 69
+fn midi_number(pitch: &str) -> Result<u8, String> {
+    let mut chars = pitch.chars();
+    let semitone = match chars.next().map(|c| c.to_ascii_uppercase()) {
+        Some('C') => 0,
+        Some('D') => 2,
+        Some('E') => 4,
+        Some('F') => 5,
+        Some('G') => 7,
+        Some('A') => 9,
+        Some('B') => 11,
+        _ => return Err(format!("{}: notes go from C to B", pitch)),
+    };
+    let rest = chars.as_str();
+    let (semitone, octave) = if let Some(octave) = rest.strip_prefix('#') {
+        (semitone + 1, octave)
+    } else if let Some(octave) = rest.strip_prefix('b') {
+        (semitone - 1, octave)
+    } else {
+        (semitone, rest)
+    };
+    let octave: i32 = octave
+        .parse()
+        .map_err(|_| format!("{}: the note needs an octave, like C4", pitch))?;
+    let number = (octave + 1) * 12 + semitone;
+    if (0..REST as i32).contains(&number) {
+        Ok(number as u8)
+    } else {
+        Err(format!("{}: too high or too low for MIDI", pitch))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_melodies() {
+        let notes = melody("C4 a4:500 F#3 Bb2 r:100 C-1", 250, 200).unwrap();
+        assert_eq!(
+            notes,
+            vec![
+                Note::new(60, 250, 200),
+                Note::new(69, 500, 200),
+                Note::new(54, 250, 200),
+                Note::new(46, 250, 200),
+                Note::new(REST, 100, 0),
+                Note::new(0, 250, 200),
+            ]
+        );
+        assert!(melody("", 250, 200).unwrap().is_empty());
+        for bad in &["H4", "C", "C4:0", "C4:3000", "C4:long", "G#9", "Cb-1"] {
+            assert!(melody(bad, 250, 200).is_err(), "{}", bad);
+        }
+        let long = vec!["C4"; MAX_NOTES + 1].join(" ");
+        assert!(melody(&long, 250, 200).is_err());
+    }
+}
@@ -0,0 +1,1005 @@
+use nannou_osc as osc;
+use nannou_osc::Type;
+use std::convert::TryFrom;
+use std::fmt;
+
+//where the toio-osc bridge sends us cube info
+pub const PORT: u16 = 3333;
+//where the toio-osc bridge listens for commands
+pub const TARGET_PORT: u16 = 3334;
+
+/// Messages sent by the bridge, and by whoever runs the show.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InboundMessage {
+    /// `/position`: host id, cube id, x, y, angle, sensor x, sensor y
+    /// (mat coordinates, angle in degrees)
+    Position {
+        host: i32,
+        cube: i32,
+        x: i32,
+        y: i32,
+        angle: i32,
+        sensor_x: i32,
+        sensor_y: i32,
+    },
+    /// `/button`: host id, cube id, pressed state
+    Button { host: i32, cube: i32, pressed: bool },
+    /// `/position_missed`: host id, cube id, the cube can't read the mat anymore
+    PositionMissed { host: i32, cube: i32 },
+    /// `/standard_id`: host id, cube id, id of the card or sticker under the cube, angle
+    /// in degrees
+    StandardId {
+        host: i32,
+        cube: i32,
+        value: u32,
+        angle: i32,
+    },
+    /// `/motion`: host id, cube id, flat, collision, double tap, posture (1 to 6, 1 is
+    /// upright), shake level (0 to 10)
+    Motion {
+        host: i32,
+        cube: i32,
+        flat: bool,
+        collision: bool,
+        double_tap: bool,
+        posture: i32,
+        shake: i32,
+    },
+    /// `/battery`: host id, cube id, battery level in %
+    Battery { host: i32, cube: i32, level: i32 },
+    /// `/target_result`: host id, cube id, id of the target request, how it ended (0 is
+    /// reached, see `cube::TargetResult`)
+    TargetResult {
+        host: i32,
+        cube: i32,
+        request: i32,
+        result: i32,
+    },
+    /// `/estop`: 1 stops every cube until a 0 comes, from a stage manager's console
+    EmergencyStop { engaged: bool },
+}
+
+//the longest a cube keeps a colour, it counts in 10 ms steps up to 255
+pub const MAX_LIGHT_MS: i32 = 2550;
+
+/// A colour of the LED of a cube, for duration ms (0 keeps it on).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LightStep {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub duration: i32,
+}
+
+impl LightStep {
+    pub fn new(r: u8, g: u8, b: u8, duration: i32) -> Self {
+        LightStep { r, g, b, duration }
+    }
+}
+
+//the sound effects of the cubes go from 0 to this
+pub const MAX_SOUND_EFFECT: u8 = 10;
+//the MIDI note number the cubes take for a rest
+pub const REST: u8 = 128;
+//the most notes a cube takes at once
+pub const MAX_NOTES: usize = 59;
+//the longest note, same 10 ms steps as the lights
+pub const MAX_NOTE_MS: i32 = 2550;
+
+/// A MIDI note (or `REST`) for duration ms, as loud as volume.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Note {
+    pub note: u8,
+    pub duration: i32,
+    pub volume: u8,
+}
+
+impl Note {
+    pub fn new(note: u8, duration: i32, volume: u8) -> Self {
+        Note {
+            note,
+            duration,
+            volume,
+        }
+    }
+}
+
+/// How a cube drives to a target on its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MovementType {
+    //turns while moving, backwards when it's shorter
+    Curve,
+    //same, never backwards
+    CurveForward,
+    //turns towards the target, then goes straight
+    TurnFirst,
+}
+
+/// How the speed of a cube changes on its way to a target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpeedChange {
+    Constant,
+    Accelerate,
+    Decelerate,
+    AccelerateDecelerate,
+}
+
+//the numbers the cube uses, in the order of the enums
+impl MovementType {
+    pub fn id(self) -> i32 {
+        self as i32
+    }
+    pub fn from_id(id: i32) -> Option<Self> {
+        [
+            MovementType::Curve,
+            MovementType::CurveForward,
+            MovementType::TurnFirst,
+        ]
+        .get(usize::try_from(id).ok()?)
+        .copied()
+    }
+}
+
+impl SpeedChange {
+    pub fn id(self) -> i32 {
+        self as i32
+    }
+    pub fn from_id(id: i32) -> Option<Self> {
+        [
+            SpeedChange::Constant,
+            SpeedChange::Accelerate,
+            SpeedChange::Decelerate,
+            SpeedChange::AccelerateDecelerate,
+        ]
+        .get(usize::try_from(id).ok()?)
+        .copied()
+    }
+}
+
+/// How a cube drives to its targets, the same for all of them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TargetConfig {
+    pub movement: MovementType,
+    //motor value, 10 to 115
+    pub max_speed: u8,
+    pub speed_change: SpeedChange,
+    //the cube gives up after that many s, 0 is its default of 10 s
+    pub timeout: u8,
+}
+
+impl Default for TargetConfig {
+    fn default() -> Self {
+        TargetConfig {
+            movement: MovementType::Curve,
+            max_speed: 80,
+            speed_change: SpeedChange::Constant,
+            timeout: 0,
+        }
+    }
+}
+
+/// Where a cube drives to, in mat coordinates, angle in degrees or None to end facing
+/// wherever.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MatTarget {
+    pub x: i32,
+    pub y: i32,
+    pub angle: Option<i32>,
+}
+
+/// Commands sent to the bridge.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutboundCommand {
+    /// `/motor`: cube id, left speed, right speed, duration in ms
+    Motor {
+        cube: i32,
+        left: i32,
+        right: i32,
+        duration: i32,
+    },
+    /// `/led`: cube id, red, green, blue, duration in ms (0 keeps it on)
+    Light { cube: i32, step: LightStep },
+    /// `/led_sequence`: cube id, how many times (0 forever), then red, green, blue and
+    /// duration of every step
+    LightSequence {
+        cube: i32,
+        repeat: i32,
+        steps: Vec<LightStep>,
+    },
+    /// `/target`: cube id, request id, x, y, angle (-1 for any), max speed, movement
+    /// type, speed change type, timeout in s, the cube answers with a `/target_result`
+    Target {
+        cube: i32,
+        request: u8,
+        target: MatTarget,
+        config: TargetConfig,
+    },
+    /// `/targets`: cube id, request id, max speed, movement type, speed change type,
+    /// timeout in s, then x, y and angle of every target, one `/target_result` at the end
+    Targets {
+        cube: i32,
+        request: u8,
+        targets: Vec<MatTarget>,
+        config: TargetConfig,
+    },
+    /// `/sound`: cube id, id of a sound effect of the cube, volume
+    SoundEffect { cube: i32, effect: i32, volume: i32 },
+    /// `/midi`: cube id, how many times (0 forever), then note, duration in ms and
+    /// volume of every note
+    Midi {
+        cube: i32,
+        repeat: i32,
+        notes: Vec<Note>,
+    },
+}
+
+impl OutboundCommand {
+    //the controllers return [left, right]
+    pub fn motor(cube: i32, speeds: [i32; 2], duration: i32) -> Self {
+        OutboundCommand::Motor {
+            cube,
+            left: speeds[0],
+            right: speeds[1],
+            duration,
+        }
+    }
+    pub fn light(cube: i32, step: LightStep) -> Self {
+        OutboundCommand::Light { cube, step }
+    }
+    pub fn light_sequence(cube: i32, steps: Vec<LightStep>, repeat: i32) -> Self {
+        OutboundCommand::LightSequence {
+            cube,
+            repeat,
+            steps,
+        }
+    }
+    pub fn sound_effect(cube: i32, effect: i32, volume: i32) -> Self {
+        OutboundCommand::SoundEffect {
+            cube,
+            effect,
+            volume,
+        }
+    }
+    pub fn midi(cube: i32, notes: Vec<Note>, repeat: i32) -> Self {
+        OutboundCommand::Midi {
+            cube,
+            repeat,
+            notes,
+        }
+    }
+    //what an emergency stop holds back
+    pub fn moves_cubes(&self) -> bool {
+        match self {
+            OutboundCommand::Motor { .. }
+            | OutboundCommand::Target { .. }
+            | OutboundCommand::Targets { .. } => true,
+            OutboundCommand::Light { .. }
+            | OutboundCommand::LightSequence { .. }
+            | OutboundCommand::SoundEffect { .. }
+            | OutboundCommand::Midi { .. } => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProtocolError {
+    UnknownAddress(String),
+    WrongArity {
+        addr: String,
+        expected: usize,
+        found: usize,
+    },
+    WrongType {
+        addr: String,
+        index: usize,
+        expected: &'static str,
+        found: Type,
+    },
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProtocolError::UnknownAddress(addr) => write!(f, "unknown OSC address {}", addr),
+            ProtocolError::WrongArity {
+                addr,
+                expected,
+                found,
+            } => write!(f, "{} expects {} arguments, got {}", addr, expected, found),
+            ProtocolError::WrongType {
+                addr,
+                index,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{} argument {} should be {}, got {:?}",
+                addr, index, expected, found
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {}
+
+//read the args of a message that should be exactly n ints
+fn int_args(message: &osc::Message, n: usize) -> Result<Vec<i32>, ProtocolError> {
+    let found = message.args.as_ref().map_or(0, |args| args.len());
+    if found != n {
+        return Err(ProtocolError::WrongArity {
+            addr: message.addr.clone(),
+            expected: n,
+            found,
+        });
+    }
+    ints(message)
+}
+
+//same for a message with a head of n ints followed by any number of groups of m ints
+fn repeated_int_args(
+    message: &osc::Message,
+    n: usize,
+    m: usize,
+) -> Result<Vec<i32>, ProtocolError> {
+    let found = message.args.as_ref().map_or(0, |args| args.len());
+    if found < n || !(found - n).is_multiple_of(m) {
+        return Err(ProtocolError::WrongArity {
+            addr: message.addr.clone(),
+            //the next count that would do
+            expected: n + found.saturating_sub(n).div_ceil(m) * m,
+            found,
+        });
+    }
+    ints(message)
+}
+
+fn ints(message: &osc::Message) -> Result<Vec<i32>, ProtocolError> {
+    let args: &[Type] = match &message.args {
+        Some(args) => args,
+        None => &[],
+    };
+    args.iter()
+        .enumerate()
+        .map(|(index, arg)| match arg {
+            Type::Int(i) => Ok(*i),
+            _ => Err(ProtocolError::WrongType {
+                addr: message.addr.clone(),
+                index,
+                expected: "Int",
+                found: arg.clone(),
+            }),
+        })
+        .collect()
+}
+
+impl TryFrom<osc::Message> for InboundMessage {
+    type Error = ProtocolError;
+
+    fn try_from(message: osc::Message) -> Result<Self, Self::Error> {
+        match message.addr.as_ref() {
+            "/position" => {
+                let marg = int_args(&message, 7)?;
+                Ok(InboundMessage::Position {
+                    host: marg[0],
+                    cube: marg[1],
+                    x: marg[2],
+                    y: marg[3],
+                    angle: marg[4],
+                    sensor_x: marg[5],
+                    sensor_y: marg[6],
+                })
+            }
+            "/button" => {
+                let marg = int_args(&message, 3)?;
+                Ok(InboundMessage::Button {
+                    host: marg[0],
+                    cube: marg[1],
+                    pressed: marg[2] != 0,
+                })
+            }
+            "/position_missed" => {
+                let marg = int_args(&message, 2)?;
+                Ok(InboundMessage::PositionMissed {
+                    host: marg[0],
+                    cube: marg[1],
+                })
+            }
+            "/standard_id" => {
+                let marg = int_args(&message, 4)?;
+                //ids are unsigned, a negative one is a broken bridge rather than a big id
+                let value = u32::try_from(marg[2]).map_err(|_| ProtocolError::WrongType {
+                    addr: message.addr.clone(),
+                    index: 2,
+                    expected: "non-negative Int",
+                    found: Type::Int(marg[2]),
+                })?;
+                Ok(InboundMessage::StandardId {
+                    host: marg[0],
+                    cube: marg[1],
+                    value,
+                    angle: marg[3],
+                })
+            }
+            "/motion" => {
+                let marg = int_args(&message, 7)?;
+                Ok(InboundMessage::Motion {
+                    host: marg[0],
+                    cube: marg[1],
+                    flat: marg[2] != 0,
+                    collision: marg[3] != 0,
+                    double_tap: marg[4] != 0,
+                    posture: marg[5],
+                    shake: marg[6],
+                })
+            }
+            "/battery" => {
+                let marg = int_args(&message, 3)?;
+                Ok(InboundMessage::Battery {
+                    host: marg[0],
+                    cube: marg[1],
+                    level: marg[2],
+                })
+            }
+            "/target_result" => {
+                let marg = int_args(&message, 4)?;
+                Ok(InboundMessage::TargetResult {
+                    host: marg[0],
+                    cube: marg[1],
+                    request: marg[2],
+                    result: marg[3],
+                })
+            }
+            "/estop" => {
+                let marg = int_args(&message, 1)?;
+                Ok(InboundMessage::EmergencyStop {
+                    engaged: marg[0] != 0,
+                })
+            }
+            _ => Err(ProtocolError::UnknownAddress(message.addr)),
+        }
+    }
+}
+
+//what the bridge sends, used by the simulator
+impl From<InboundMessage> for osc::Message {
+    fn from(message: InboundMessage) -> Self {
+        match message {
+            InboundMessage::Position {
+                host,
+                cube,
+                x,
+                y,
+                angle,
+                sensor_x,
+                sensor_y,
+            } => osc::msg(
+                "/position",
+                vec![
+                    Type::Int(host),
+                    Type::Int(cube),
+                    Type::Int(x),
+                    Type::Int(y),
+                    Type::Int(angle),
+                    Type::Int(sensor_x),
+                    Type::Int(sensor_y),
+                ],
+            ),
+            InboundMessage::Button {
+                host,
+                cube,
+                pressed,
+            } => osc::msg(
+                "/button",
+                vec![Type::Int(host), Type::Int(cube), Type::Int(pressed as i32)],
+            ),
+            InboundMessage::PositionMissed { host, cube } => {
+                osc::msg("/position_missed", vec![Type::Int(host), Type::Int(cube)])
+            }
+            InboundMessage::StandardId {
+                host,
+                cube,
+                value,
+                angle,
+            } => osc::msg(
+                "/standard_id",
+                vec![
+                    Type::Int(host),
+                    Type::Int(cube),
+                    Type::Int(value as i32),
+                    Type::Int(angle),
+                ],
+            ),
+            InboundMessage::Motion {
+                host,
+                cube,
+                flat,
+                collision,
+                double_tap,
+                posture,
+                shake,
+            } => osc::msg(
+                "/motion",
+                vec![
+                    Type::Int(host),
+                    Type::Int(cube),
+                    Type::Int(flat as i32),
+                    Type::Int(collision as i32),
+                    Type::Int(double_tap as i32),
+                    Type::Int(posture),
+                    Type::Int(shake),
+                ],
+            ),
+            InboundMessage::Battery { host, cube, level } => osc::msg(
+                "/battery",
+                vec![Type::Int(host), Type::Int(cube), Type::Int(level)],
+            ),
+            InboundMessage::TargetResult {
+                host,
+                cube,
+                request,
+                result,
+            } => osc::msg(
+                "/target_result",
+                vec![
+                    Type::Int(host),
+                    Type::Int(cube),
+                    Type::Int(request),
+                    Type::Int(result),
+                ],
+            ),
+            InboundMessage::EmergencyStop { engaged } => {
+                osc::msg("/estop", vec![Type::Int(engaged as i32)])
+            }
+        }
+    }
+}
+
+impl From<InboundMessage> for osc::Packet {
+    fn from(message: InboundMessage) -> Self {
+        osc::Packet::Message(message.into())
+    }
+}
+
+//what the bridge receives, used by the simulator
+impl TryFrom<osc::Message> for OutboundCommand {
+    type Error = ProtocolError;
+
+    fn try_from(message: osc::Message) -> Result<Self, Self::Error> {
+        match message.addr.as_ref() {
+            "/motor" => {
+                let marg = int_args(&message, 4)?;
+                Ok(OutboundCommand::Motor {
+                    cube: marg[0],
+                    left: marg[1],
+                    right: marg[2],
+                    duration: marg[3],
+                })
+            }
+            "/led" => {
+                let marg = int_args(&message, 5)?;
+                Ok(OutboundCommand::light(marg[0], light_step(&marg[1..])))
+            }
+            "/led_sequence" => {
+                let marg = repeated_int_args(&message, 2, 4)?;
+                Ok(OutboundCommand::LightSequence {
+                    cube: marg[0],
+                    repeat: marg[1],
+                    steps: marg[2..].chunks(4).map(light_step).collect(),
+                })
+            }
+            "/target" => {
+                let marg = int_args(&message, 9)?;
+                Ok(OutboundCommand::Target {
+                    cube: marg[0],
+                    request: byte(marg[1]),
+                    target: mat_target(&marg[2..5]),
+                    config: target_config(&marg[5..9]),
+                })
+            }
+            "/targets" => {
+                let marg = repeated_int_args(&message, 6, 3)?;
+                Ok(OutboundCommand::Targets {
+                    cube: marg[0],
+                    request: byte(marg[1]),
+                    targets: marg[6..].chunks(3).map(mat_target).collect(),
+                    config: target_config(&marg[2..6]),
+                })
+            }
+            "/sound" => {
+                let marg = int_args(&message, 3)?;
+                Ok(OutboundCommand::sound_effect(marg[0], marg[1], marg[2]))
+            }
+            "/midi" => {
+                let marg = repeated_int_args(&message, 2, 3)?;
+                Ok(OutboundCommand::Midi {
+                    cube: marg[0],
+                    repeat: marg[1],
+                    notes: marg[2..]
+                        .chunks(3)
+                        .map(|n| Note::new(byte(n[0]), n[1], byte(n[2])))
+                        .collect(),
+                })
+            }
+            _ => Err(ProtocolError::UnknownAddress(message.addr)),
+        }
+    }
+}
+
+fn byte(i: i32) -> u8 {
+    i.clamp(0, 255) as u8
+}
+
+//x, y, angle
+fn mat_target(args: &[i32]) -> MatTarget {
+    MatTarget {
+        x: args[0],
+        y: args[1],
+        angle: Some(args[2]).filter(|a| *a >= 0),
+    }
+}
+
+fn mat_target_args(target: &MatTarget) -> Vec<Type> {
+    vec![
+        Type::Int(target.x),
+        Type::Int(target.y),
+        Type::Int(target.angle.unwrap_or(-1)),
+    ]
+}
+
+//max speed, movement type, speed change type, timeout, unknown types get the defaults
+fn target_config(args: &[i32]) -> TargetConfig {
+    let default = TargetConfig::default();
+    TargetConfig {
+        max_speed: byte(args[0]),
+        movement: MovementType::from_id(args[1]).unwrap_or(default.movement),
+        speed_change: SpeedChange::from_id(args[2]).unwrap_or(default.speed_change),
+        timeout: byte(args[3]),
+    }
+}
+
+fn target_config_args(config: &TargetConfig) -> Vec<Type> {
+    vec![
+        Type::Int(config.max_speed as i32),
+        Type::Int(config.movement.id()),
+        Type::Int(config.speed_change.id()),
+        Type::Int(config.timeout as i32),
+    ]
+}
+
+//r, g, b, duration
+fn light_step(args: &[i32]) -> LightStep {
+    LightStep::new(byte(args[0]), byte(args[1]), byte(args[2]), args[3])
+}
+
+fn light_step_args(step: &LightStep) -> Vec<Type> {
+    vec![
+        Type::Int(step.r as i32),
+        Type::Int(step.g as i32),
+        Type::Int(step.b as i32),
+        Type::Int(step.duration),
+    ]
+}
+
+impl From<OutboundCommand> for osc::Message {
+    fn from(command: OutboundCommand) -> Self {
+        match command {
+            OutboundCommand::Motor {
+                cube,
+                left,
+                right,
+                duration,
+            } => osc::msg(
+                "/motor",
+                vec![
+                    Type::Int(cube),
+                    Type::Int(left),
+                    Type::Int(right),
+                    Type::Int(duration),
+                ],
+            ),
+            OutboundCommand::Light { cube, step } => {
+                let mut args = vec![Type::Int(cube)];
+                args.extend(light_step_args(&step));
+                osc::msg("/led", args)
+            }
+            OutboundCommand::LightSequence {
+                cube,
+                repeat,
+                steps,
+            } => {
+                let mut args = vec![Type::Int(cube), Type::Int(repeat)];
+                args.extend(steps.iter().flat_map(light_step_args));
+                osc::msg("/led_sequence", args)
+            }
+            OutboundCommand::Target {
+                cube,
+                request,
+                target,
+                config,
+            } => {
+                let mut args = vec![Type::Int(cube), Type::Int(request as i32)];
+                args.extend(mat_target_args(&target));
+                args.extend(target_config_args(&config));
+                osc::msg("/target", args)
+            }
+            OutboundCommand::Targets {
+                cube,
+                request,
+                targets,
+                config,
+            } => {
+                let mut args = vec![Type::Int(cube), Type::Int(request as i32)];
+                args.extend(target_config_args(&config));
+                args.extend(targets.iter().flat_map(mat_target_args));
+                osc::msg("/targets", args)
+            }
+            OutboundCommand::SoundEffect {
+                cube,
+                effect,
+                volume,
+            } => osc::msg(
+                "/sound",
+                vec![Type::Int(cube), Type::Int(effect), Type::Int(volume)],
+            ),
+            OutboundCommand::Midi {
+                cube,
+                repeat,
+                notes,
+            } => {
+                let mut args = vec![Type::Int(cube), Type::Int(repeat)];
+                args.extend(notes.iter().flat_map(|n| {
+                    vec![
+                        Type::Int(n.note as i32),
+                        Type::Int(n.duration),
+                        Type::Int(n.volume as i32),
+                    ]
+                }));
+                osc::msg("/midi", args)
+            }
+        }
+    }
+}
+
+impl From<OutboundCommand> for osc::Packet {
+    fn from(command: OutboundCommand) -> Self {
+        osc::Packet::Message(command.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ints(addr: &str, args: &[i32]) -> osc::Message {
+        osc::msg(addr, args.iter().map(|&i| Type::Int(i)).collect())
+    }
+
+    //every inbound message, with the OSC it comes from
+    fn inbound_cases() -> Vec<(osc::Message, InboundMessage)> {
+        vec![
+            (
+                ints("/position", &[0, 1, 100, 200, 90, 101, 201]),
+                InboundMessage::Position {
+                    host: 0,
+                    cube: 1,
+                    x: 100,
+                    y: 200,
+                    angle: 90,
+                    sensor_x: 101,
+                    sensor_y: 201,
+                },
+            ),
+            (
+                ints("/button", &[1, 2, 1]),
+                InboundMessage::Button {
+                    host: 1,
+                    cube: 2,
+                    pressed: true,
+                },
+            ),
+            (
+                ints("/position_missed", &[0, 3]),
+                InboundMessage::PositionMissed { host: 0, cube: 3 },
+            ),
+            (
+                ints("/standard_id", &[0, 1, 3_670_016, 45]),
+                InboundMessage::StandardId {
+                    host: 0,
+                    cube: 1,
+                    value: 3_670_016,
+                    angle: 45,
+                },
+            ),
+            (
+                ints("/motion", &[0, 1, 1, 0, 1, 3, 4]),
+                InboundMessage::Motion {
+                    host: 0,
+                    cube: 1,
+                    flat: true,
+                    collision: false,
+                    double_tap: true,
+                    posture: 3,
+                    shake: 4,
+                },
+            ),
+            (
+                ints("/battery", &[0, 1, 60]),
+                InboundMessage::Battery {
+                    host: 0,
+                    cube: 1,
+                    level: 60,
+                },
+            ),
+            (
+                ints("/target_result", &[0, 1, 7, 1]),
+                InboundMessage::TargetResult {
+                    host: 0,
+                    cube: 1,
+                    request: 7,
+                    result: 1,
+                },
+            ),
+            (
+                ints("/estop", &[1]),
+                InboundMessage::EmergencyStop { engaged: true },
+            ),
+        ]
+    }
+
+    #[test]
+    fn every_inbound_message_parses() {
+        for (message, expected) in inbound_cases() {
+            assert_eq!(InboundMessage::try_from(message), Ok(expected));
+        }
+    }
+
+    #[test]
+    fn inbound_messages_go_both_ways() {
+        //what the simulator sends is what the app reads
+        for (message, inbound) in inbound_cases() {
+            assert_eq!(osc::Message::from(inbound.clone()), message);
+            assert_eq!(InboundMessage::try_from(message), Ok(inbound));
+        }
+    }
+
+    #[test]
+    fn motor_commands_go_both_ways() {
+        let command = OutboundCommand::motor(2, [50, -30], 100);
+        let message: osc::Message = command.clone().into();
+        assert_eq!(message, ints("/motor", &[2, 50, -30, 100]));
+        assert_eq!(OutboundCommand::try_from(message), Ok(command));
+        assert_eq!(
+            OutboundCommand::try_from(ints("/motor", &[2, 50, -30])),
+            Err(ProtocolError::WrongArity {
+                addr: "/motor".to_string(),
+                expected: 4,
+                found: 3,
+            })
+        );
+    }
+
+    #[test]
+    fn bad_inbound_messages_say_why() {
+        assert_eq!(
+            InboundMessage::try_from(ints("/jump", &[0, 1])),
+            Err(ProtocolError::UnknownAddress("/jump".to_string()))
+        );
+        assert_eq!(
+            InboundMessage::try_from(ints("/button", &[0, 1])),
+            Err(ProtocolError::WrongArity {
+                addr: "/button".to_string(),
+                expected: 3,
+                found: 2,
+            })
+        );
+        assert_eq!(
+            InboundMessage::try_from(osc::msg("/estop", vec![])),
+            Err(ProtocolError::WrongArity {
+                addr: "/estop".to_string(),
+                expected: 1,
+                found: 0,
+            })
+        );
+        let message = osc::msg(
+            "/battery",
+            vec![Type::Int(0), Type::Int(1), Type::Float(60.0)],
+        );
+        assert_eq!(
+            InboundMessage::try_from(message),
+            Err(ProtocolError::WrongType {
+                addr: "/battery".to_string(),
+                index: 2,
+                expected: "Int",
+                found: Type::Float(60.0),
+            })
+        );
+        //not wrapped around to a big card id
+        assert!(matches!(
+            InboundMessage::try_from(ints("/standard_id", &[0, 1, -5, 0])),
+            Err(ProtocolError::WrongType { index: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn sound_commands_go_both_ways() {
+        let notes = vec![Note::new(60, 200, 255), Note::new(REST, 100, 0)];
+        for command in [
+            OutboundCommand::sound_effect(1, 3, 200),
+            OutboundCommand::midi(2, notes, 1),
+        ]
+        .iter()
+        .cloned()
+        {
+            let message: osc::Message = command.clone().into();
+            assert_eq!(OutboundCommand::try_from(message), Ok(command));
+        }
+    }
+
+    #[test]
+    fn target_commands_go_both_ways() {
+        let config = TargetConfig {
+            movement: MovementType::TurnFirst,
+            max_speed: 50,
+            speed_change: SpeedChange::AccelerateDecelerate,
+            timeout: 5,
+        };
+        let a = MatTarget {
+            x: 100,
+            y: 200,
+            angle: Some(90),
+        };
+        let b = MatTarget {
+            x: 300,
+            y: 200,
+            angle: None,
+        };
+        for command in [
+            OutboundCommand::Target {
+                cube: 1,
+                request: 7,
+                target: a,
+                config,
+            },
+            OutboundCommand::Targets {
+                cube: 1,
+                request: 8,
+                targets: vec![a, b],
+                config,
+            },
+        ]
+        .iter()
+        .cloned()
+        {
+            assert!(command.moves_cubes());
+            let message: osc::Message = command.clone().into();
+            assert_eq!(OutboundCommand::try_from(message), Ok(command));
+        }
+    }
+
+    #[test]
+    fn light_commands_go_both_ways() {
+        let steps = vec![
+            LightStep::new(255, 0, 0, 200),
+            LightStep::new(0, 0, 255, 300),
+        ];
+        for command in [
+            OutboundCommand::light(1, LightStep::new(0, 255, 0, 0)),
+            OutboundCommand::light_sequence(2, steps, 3),
+        ]
+        .iter()
+        .cloned()
+        {
+            let message: osc::Message = command.clone().into();
+            assert_eq!(OutboundCommand::try_from(message), Ok(command));
+        }
+        let message = osc::msg("/led_sequence", vec![Type::Int(1); 5]);
+        assert_eq!(
+            OutboundCommand::try_from(message),
+            Err(ProtocolError::WrongArity {
+                addr: "/led_sequence".to_string(),
+                expected: 6,
+                found: 5
+            })
+        );
+    }
+}
@@ -0,0 +1,305 @@
+use crate::controllers::{aim_angle, aimany};
+use crate::cube::{TargetResult, CENTER_X, CENTER_Y};
+use crate::kinematics::KinematicCube;
+use crate::protocol::{InboundMessage, MatTarget, OutboundCommand};
+use nannou::prelude::*;
+
+//ms between two /position messages of a cube
+pub const POSITION_PERIOD: u64 = 30;
+//limits of the mat the cubes can drive on, in mat coordinates
+const MAT_MIN: f32 = 45.0;
+const MAT_MAX: f32 = 455.0;
+
+//close enough to a target, and to its angle
+const TARGET_DISTANCE: f32 = 15.0;
+const TARGET_ANGLE: f32 = 0.35;
+
+//a cube driving itself through its targets (app frame), the way it likes: it doesn't
+//care about the movement type and speeds asked
+struct Navigation {
+    request: u8,
+    targets: Vec<(f32, f32, Option<f32>)>,
+    until: u64,
+}
+
+/// Pretends to be a toio-osc bridge with `cubes.len()` cubes on a mat.
+pub struct Simulator {
+    pub host: i32,
+    pub cubes: Vec<KinematicCube>,
+    //what each cube is driving to on its own
+    navigations: Vec<Option<Navigation>>,
+    //answers to the target requests, until someone takes them
+    results: Vec<InboundMessage>,
+}
+
+impl Simulator {
+    //n cubes in a circle around the center of the mat, facing out
+    pub fn new(host: i32, n: usize) -> Self {
+        let cubes = (0..n)
+            .map(|i| {
+                let a = i as f32 / n as f32 * TAU;
+                KinematicCube::new((a.cos() * 60.0, a.sin() * 60.0, a))
+            })
+            .collect();
+        Simulator {
+            host,
+            cubes,
+            navigations: (0..n).map(|_| None).collect(),
+            results: Vec::new(),
+        }
+    }
+    pub fn handle(&mut self, command: OutboundCommand, now: u64) {
+        match command {
+            OutboundCommand::Motor {
+                cube,
+                left,
+                right,
+                duration,
+            } => {
+                if cube >= 0 && (cube as usize) < self.cubes.len() {
+                    //the cube stops driving itself on any motor command
+                    self.end_navigation(cube, TargetResult::Overridden);
+                    let c = &mut self.cubes[cube as usize];
+                    c.command([left, right], duration.max(0) as u64, now);
+                }
+            }
+            OutboundCommand::Target {
+                cube,
+                request,
+                target,
+                config,
+            } => self.navigate(cube, request, vec![target], config.timeout, now),
+            OutboundCommand::Targets {
+                cube,
+                request,
+                targets,
+                config,
+            } => self.navigate(cube, request, targets, config.timeout, now),
+            //the simulated cubes have no LED and no speaker
+            OutboundCommand::Light { .. }
+            | OutboundCommand::LightSequence { .. }
+            | OutboundCommand::SoundEffect { .. }
+            | OutboundCommand::Midi { .. } => {}
+        }
+    }
+    fn navigate(&mut self, cube: i32, request: u8, targets: Vec<MatTarget>, timeout: u8, now: u64) {
+        if cube < 0 || cube as usize >= self.cubes.len() {
+            return;
+        }
+        self.end_navigation(cube, TargetResult::Overridden);
+        let targets = targets
+            .iter()
+            .map(|t| {
+                let angle = t.angle.map(|a| (-a as f32).to_radians());
+                ((t.x - CENTER_X) as f32, (CENTER_Y - t.y) as f32, angle)
+            })
+            .collect();
+        //0 is the default of the cubes
+        let timeout = if timeout == 0 { 10 } else { timeout as u64 };
+        self.navigations[cube as usize] = Some(Navigation {
+            request,
+            targets,
+            until: now + timeout * 1000,
+        });
+    }
+    fn end_navigation(&mut self, cube: i32, result: TargetResult) {
+        if let Some(navigation) = self.navigations[cube as usize].take() {
+            self.cubes[cube as usize].command([0, 0], 0, 0);
+            self.results.push(InboundMessage::TargetResult {
+                host: self.host,
+                cube,
+                request: navigation.request as i32,
+                result: result.id(),
+            });
+        }
+    }
+    //the cubes driving themselves pick their motor speeds
+    fn steer(&mut self, now: u64) {
+        for i in 0..self.cubes.len() {
+            let navigation = match &mut self.navigations[i] {
+                Some(navigation) => navigation,
+                None => continue,
+            };
+            if now > navigation.until {
+                self.end_navigation(i as i32, TargetResult::Timeout);
+                continue;
+            }
+            let (x, y, angle) = self.cubes[i].pose;
+            let speeds = loop {
+                let (tx, ty, ta) = match navigation.targets.first() {
+                    Some(target) => *target,
+                    None => break None,
+                };
+                if vec2(tx - x, ty - y).magnitude() > TARGET_DISTANCE {
+                    break Some(aimany(x, y, angle, tx, ty));
+                }
+                let off = ta.map_or(0.0, |ta| ((ta - angle + PI).rem_euclid(TAU) - PI).abs());
+                if off > TARGET_ANGLE {
+                    break Some(aim_angle(x, y, angle, ta.unwrap_or(angle)));
+                }
+                navigation.targets.remove(0);
+            };
+            match speeds {
+                Some(speeds) => self.cubes[i].command(speeds, 0, now),
+                None => self.end_navigation(i as i32, TargetResult::Reached),
+            }
+        }
+    }
+    //what the cubes answered to their target requests since the last call
+    pub fn results(&mut self) -> Vec<InboundMessage> {
+        std::mem::take(&mut self.results)
+    }
+    //advance every cube by dt ms
+    pub fn step(&mut self, now: u64, dt: u64) {
+        self.steer(now);
+        let (cx, cy) = (CENTER_X as f32, CENTER_Y as f32);
+        for cube in self.cubes.iter_mut() {
+            cube.step(now, dt);
+            //the mat has borders
+            let (x, y, angle) = cube.pose;
+            cube.pose = (
+                x.clamp(MAT_MIN - cx, MAT_MAX - cx),
+                y.clamp(cy - MAT_MAX, cy - MAT_MIN),
+                angle,
+            );
+        }
+    }
+    //what the bridge would report, in mat coordinates
+    pub fn positions(&self) -> Vec<InboundMessage> {
+        self.cubes
+            .iter()
+            .enumerate()
+            .map(|(i, c)| {
+                let (x, y, angle) = c.pose;
+                let x = (x + CENTER_X as f32).round() as i32;
+                let y = (CENTER_Y as f32 - y).round() as i32;
+                InboundMessage::Position {
+                    host: self.host,
+                    cube: i as i32,
+                    x,
+                    y,
+                    angle: (-angle.to_degrees()).round().rem_euclid(360.0) as i32,
+                    sensor_x: x,
+                    sensor_y: y,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::TargetConfig;
+
+    //steps of 5ms until a cube answers, or the time is up
+    fn run_until_result(sim: &mut Simulator, from: u64, limit: u64) -> Vec<InboundMessage> {
+        let mut now = from;
+        while now < from + limit {
+            now += 5;
+            sim.step(now, 5);
+            let results = sim.results();
+            if !results.is_empty() {
+                return results;
+            }
+        }
+        Vec::new()
+    }
+
+    fn target(cube: i32, request: u8, x: i32, y: i32) -> OutboundCommand {
+        OutboundCommand::Target {
+            cube,
+            request,
+            target: MatTarget { x, y, angle: None },
+            config: TargetConfig::default(),
+        }
+    }
+
+    #[test]
+    fn cubes_start_around_the_center() {
+        let sim = Simulator::new(1, 2);
+        let positions = sim.positions();
+        assert_eq!(
+            positions[0],
+            InboundMessage::Position {
+                host: 1,
+                cube: 0,
+                x: 310,
+                y: 250,
+                angle: 0,
+                sensor_x: 310,
+                sensor_y: 250,
+            }
+        );
+        //facing out, the other way
+        assert!(matches!(
+            positions[1],
+            InboundMessage::Position {
+                cube: 1,
+                x: 190,
+                y: 250,
+                angle: 180,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn motor_commands_drive_the_cubes() {
+        let mut sim = Simulator::new(0, 2);
+        sim.handle(OutboundCommand::motor(0, [50, 50], 0), 0);
+        //no such cube
+        sim.handle(OutboundCommand::motor(5, [50, 50], 0), 0);
+        for now in 1..=100 {
+            sim.step(now * 5, 5);
+        }
+        assert!(sim.cubes[0].pose.0 > 100.0, "{:?}", sim.cubes[0].pose);
+        assert_eq!(sim.cubes[1].pose.0, -60.0);
+
+        //and no further than the border of the mat
+        for now in 101..=2000 {
+            sim.step(now * 5, 5);
+        }
+        assert_eq!(sim.cubes[0].pose.0, MAT_MAX - CENTER_X as f32);
+    }
+
+    #[test]
+    fn cubes_answer_their_targets() {
+        let mut sim = Simulator::new(0, 1);
+        sim.handle(target(0, 3, 350, 150), 0);
+        let reached = InboundMessage::TargetResult {
+            host: 0,
+            cube: 0,
+            request: 3,
+            result: TargetResult::Reached.id(),
+        };
+        assert_eq!(run_until_result(&mut sim, 0, 10_000), vec![reached]);
+        let (x, y, _) = sim.cubes[0].pose;
+        assert!((vec2(x, y) - vec2(100.0, 100.0)).magnitude() < TARGET_DISTANCE);
+
+        //a motor command takes over
+        sim.handle(target(0, 4, 150, 350), 10_000);
+        sim.handle(OutboundCommand::motor(0, [0, 0], 0), 10_005);
+        let overridden = InboundMessage::TargetResult {
+            host: 0,
+            cube: 0,
+            request: 4,
+            result: TargetResult::Overridden.id(),
+        };
+        assert_eq!(sim.results(), vec![overridden]);
+
+        //not there after the timeout of the command, 1 s
+        let mut far = target(0, 5, 450, 450);
+        if let OutboundCommand::Target { config, .. } = &mut far {
+            config.timeout = 1;
+        }
+        sim.handle(far, 20_000);
+        let timeout = InboundMessage::TargetResult {
+            host: 0,
+            cube: 0,
+            request: 5,
+            result: TargetResult::Timeout.id(),
+        };
+        assert_eq!(run_until_result(&mut sim, 20_000, 2000), vec![timeout]);
+    }
+}
@@ -1,250 +1,1461 @@
+use crate::controllers::{aim_angle, aimany};
+use crate::cube::{CubeId, MotionEvent, Pose, TargetResult};
+use crate::protocol::{LightStep, Note, TargetConfig};
 use nannou::prelude::*;
+use std::fmt;
 
+//the group a task runs on unless told otherwise
+pub const DEFAULT_GROUP: &str = "default";
+//two cubes closer than this are touching
+pub const DIST_CLOSE: f32 = 52.0;
+//how far apart GetAway moves cubes
+pub const DIST_AWAY: f32 = 56.0;
+
+/// Something cubes can be asked to do, for task types the crate doesn't know about.
+///
+/// `data` holds the pose of every cube of the group, in order. `shifts` is where each
+/// cube of the group sits relative to its center: GetClose and GetAway update it and
+/// PairMovementShift keeps it, so that a group keeps its shape across tasks.
+///
+/// Tasks are cloned when a `Repeat` or a `LoopUntil` starts over, deriving `Clone` is
+/// enough.
+pub trait Task: TaskClone {
+    //called when the task becomes the current one of its queue
+    fn start(&mut self, _now: u64) {}
+    //motor speeds for every cube of the group, None leaves the cube alone
+    fn step(&mut self, now: u64, data: &[Pose], shifts: &mut Vec<Vector2>)
+        -> Vec<Option<[i32; 2]>>;
+    fn is_done(&self, now: u64, data: &[Pose]) -> bool;
+    //how many cubes the group needs for this task to make sense
+    fn cubes_needed(&self) -> usize {
+        0
+    }
+    //the task was paused for ms, tasks keeping their own times move them forward
+    fn delay(&mut self, _ms: u64) {}
+}
+
+pub trait TaskClone {
+    fn clone_box(&self) -> Box<dyn Task>;
+}
+
+impl<T: Task + Clone + 'static> TaskClone for T {
+    fn clone_box(&self) -> Box<dyn Task> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<dyn Task> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+//when a Parallel is done
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Join {
+    //once every task is
+    All,
+    //as soon as one of them is
+    Any,
+}
+
+/// A point a cube drives to on its own, angle None to end facing wherever.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Waypoint {
+    pub at: Vector2,
+    pub angle: Option<f32>,
+}
+
+impl Waypoint {
+    pub fn new(x: f32, y: f32, angle: Option<f32>) -> Self {
+        Waypoint {
+            at: vec2(x, y),
+            angle,
+        }
+    }
+}
+
+/// What a task does, with its parameters. Distances are in mat units, angles in
+/// radians and durations in ms.
+#[derive(Clone)]
 pub enum TaskType {
-    PairMovement,
-    SingleMovement,
-    PairMovementShift,
-    Wiggle,
-    Spin,
-    GetClose,
-    GetAway,
-    TargetAngle,
-    TargetAngles,
-    Wait,
+    //cube i goes to targets[i], done when they are all closer than distance
+    PairMovement {
+        targets: Vec<Vector2>,
+        distance: f32,
+    },
+    //the first cube of the group goes to target
+    SingleMovement {
+        target: Vector2,
+        distance: f32,
+    },
+    //the group moves its center to target, keeping its shape
+    PairMovementShift {
+        target: Vector2,
+        distance: f32,
+    },
+    Wiggle {
+        duration: u64,
+        power: f32,
+    },
+    Spin {
+        duration: u64,
+        power: f32,
+    },
+    //cubes move towards the others until the group is that wide
+    GetClose {
+        distance: f32,
+    },
+    //cubes move away from the others until no two are closer than distance
+    GetAway {
+        distance: f32,
+    },
+    //every cube turns to angle
+    TargetAngle {
+        angle: f32,
+        tolerance: f32,
+    },
+    //cube i turns to angles[i]
+    TargetAngles {
+        angles: Vec<f32>,
+        tolerance: f32,
+    },
+    Wait {
+        duration: u64,
+    },
+    //cube i drives itself to targets[i], done when every cube says it got there
+    MoveTo {
+        targets: Vec<Waypoint>,
+        config: TargetConfig,
+    },
+    //cube i drives itself through paths[i], same
+    FollowPath {
+        paths: Vec<Vec<Waypoint>>,
+        config: TargetConfig,
+    },
+    //waits until every queue with a Sync of the same name reaches it
+    Sync {
+        name: String,
+    },
+    //waits for a press on the button of cube i of the group, any of them when None
+    WaitButton {
+        cube: Option<usize>,
+    },
+    //waits for cube i of the group (any of them when None) to be put on that card (any card
+    //when None)
+    WaitCard {
+        card: Option<u32>,
+        cube: Option<usize>,
+    },
+    //waits for a card under a cube of the group and runs the task of that card, cards
+    //without a task are ignored unless there's an otherwise
+    BranchOnCard {
+        cases: Vec<(u32, ToioTask)>,
+        otherwise: Option<Box<ToioTask>>,
+    },
+    //waits for cube i of the group (any of them when None) to notice that motion
+    WaitMotion {
+        motion: MotionEvent,
+        cube: Option<usize>,
+    },
+    //lights the LED of every cube of the group, done once asked: the cube keeps it on
+    //for the duration while the next tasks run
+    SetLight {
+        light: LightStep,
+    },
+    //the steps on every cube of the group, the whole sequence n times, forever when None
+    LightSequence {
+        steps: Vec<LightStep>,
+        repeat: Option<u32>,
+    },
+    //a sound effect of the cubes on every cube of the group, done once asked like the lights
+    PlaySound {
+        effect: u8,
+        volume: u8,
+    },
+    //the notes on every cube of the group, n times, forever when None
+    PlayNotes {
+        notes: Vec<Note>,
+        repeat: Option<u32>,
+    },
+    Custom(Box<dyn Task>),
+    //the tasks one after the other
+    Sequence(Vec<ToioTask>),
+    //the tasks all at once, usually on different groups
+    Parallel {
+        tasks: Vec<ToioTask>,
+        join: Join,
+    },
+    //the task n times, forever when None
+    Repeat {
+        task: Box<ToioTask>,
+        times: Option<u32>,
+    },
+    //the task over and over until `until` is done, `until` is only checked, never run
+    //(a button, card or motion wait is checked by the engine)
+    LoopUntil {
+        task: Box<ToioTask>,
+        until: Box<ToioTask>,
+    },
+}
+
+/// What the engine does with a task that timed out.
+#[derive(Clone)]
+pub enum OnFailure {
+    //go on with the next task
+    Skip,
+    //start the task again, up to n more times, then skip it
+    Retry(u32),
+    //drop the rest of the queue
+    AbortQueue,
+    //run these tasks, then go on with the next task
+    Recover(Vec<ToioTask>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FailureReason {
+    //not done after timeout ms
+    Timeout(u64),
+    //same, but some cubes of the group were not seen at all
+    MissingCubes(u64),
+    //a cube of the group noticed a motion the task aborts on
+    Motion(MotionEvent),
+    //a cube didn't make it to its target
+    Target(TargetResult),
+    //the group has fewer cubes than the task needs, it would never get done
+    ShortOfCubes { cubes: usize, needed: usize },
+}
+
+impl fmt::Display for FailureReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FailureReason::Timeout(t) => write!(f, "not done after {}ms", t),
+            FailureReason::MissingCubes(t) => {
+                write!(f, "not done after {}ms, some cubes are missing", t)
+            }
+            FailureReason::Motion(m) => write!(f, "a cube noticed a {}", m),
+            FailureReason::Target(r) => write!(f, "a cube didn't reach its target: {}", r),
+            FailureReason::ShortOfCubes { cubes, needed } => {
+                write!(
+                    f,
+                    "the group has {} cubes, the task needs {}",
+                    cubes, needed
+                )
+            }
+        }
+    }
+}
+
+//what the queue did about a failed task
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FailureAction {
+    Skipped,
+    //started again, for the nth time
+    Retried(u32),
+    Aborted,
+    Recovering,
 }
 
+/// A step of a choreography, run on every cube of `group`.
+#[derive(Clone)]
 pub struct ToioTask {
     pub what: TaskType,
-    pub duration: Option<u64>,
-    pub targets: Option<(Vector2, Vector2)>,
-    pub target: Option<Vector2>,
-    pub target_angle: Option<f32>,
-    pub target_angles: Option<(f32, f32)>,
-    pub power: Option<f32>,
-    pub distance: Option<f32>,
+    pub group: String,
     pub start_time: u64,
+    //ms after start_time when the task fails, None waits forever
+    pub timeout: Option<u64>,
+    pub on_failure: OnFailure,
+    //how many times the task was started again after failing
+    pub retries: u32,
+    //the task fails as soon as a cube of the group notices one of these
+    pub abort_on: Vec<MotionEvent>,
+    //the running copies of the tasks of a composite, one queue per branch
+    pub(crate) children: Vec<TaskQueue>,
+    //how many times a Repeat or a LoopUntil went through its task
+    pub iterations: u32,
+    //the target requests sent to the cubes for a MoveTo or a FollowPath, sent again
+    //when empty
+    pub(crate) requests: Vec<(CubeId, u8)>,
 }
 impl ToioTask {
-    pub fn new_pair_move(x0: f32, y0: f32, x1: f32, y1: f32, d: f32) -> Self {
-        ToioTask {
-            what: TaskType::PairMovement,
-            duration: None,
-            targets: Some((vec2(x0, y0), vec2(x1, y1))),
-            target: None,
-            target_angle: None,
-            target_angles: None,
-            power: None,
-            distance: Some(d),
+    pub fn new(what: TaskType) -> Self {
+        let mut task = ToioTask {
+            what,
+            group: DEFAULT_GROUP.to_string(),
             start_time: 0,
+            timeout: None,
+            on_failure: OnFailure::Skip,
+            retries: 0,
+            abort_on: Vec::new(),
+            children: Vec::new(),
+            iterations: 0,
+            requests: Vec::new(),
+        };
+        task.reset_children();
+        task
+    }
+    //fresh copies of the tasks of a composite
+    fn reset_children(&mut self) {
+        let group = &self.group;
+        self.children = match &self.what {
+            TaskType::Sequence(tasks) => vec![TaskQueue::new(group, tasks.clone())],
+            TaskType::Parallel { tasks, .. } => tasks
+                .iter()
+                .map(|t| TaskQueue::new(group, vec![t.clone()]))
+                .collect(),
+            TaskType::Repeat { task, .. } | TaskType::LoopUntil { task, .. } => {
+                vec![TaskQueue::new(group, vec![(**task).clone()])]
+            }
+            _ => Vec::new(),
+        };
+    }
+    //a Repeat or a LoopUntil went through its task, start it over
+    pub fn next_iteration(&mut self) {
+        self.iterations += 1;
+        self.reset_children();
+    }
+    //the groups the task is driving right now, down the composites
+    pub fn groups(&self) -> Vec<&str> {
+        match self.what {
+            //these don't need the cubes on the mat
+            TaskType::Sync { .. }
+            | TaskType::WaitButton { .. }
+            | TaskType::WaitCard { .. }
+            | TaskType::WaitMotion { .. }
+            | TaskType::SetLight { .. }
+            | TaskType::LightSequence { .. }
+            | TaskType::PlaySound { .. }
+            | TaskType::PlayNotes { .. } => return vec![],
+            _ if !self.is_composite() => return vec![&self.group],
+            _ => {}
         }
+        self.children
+            .iter()
+            .filter_map(|q| q.current())
+            .flat_map(|t| t.groups())
+            .collect()
     }
-    pub fn new_pair_move_shift(x0: f32, y0: f32, d: f32) -> Self {
-        ToioTask {
-            what: TaskType::PairMovementShift,
-            duration: None,
-            targets: None,
-            target: Some(vec2(x0, y0)),
-            target_angle: None,
-            target_angles: None,
-            power: None,
-            distance: Some(d),
-            start_time: 0,
+    pub fn is_composite(&self) -> bool {
+        matches!(
+            self.what,
+            TaskType::Sequence(_)
+                | TaskType::Parallel { .. }
+                | TaskType::Repeat { .. }
+                | TaskType::LoopUntil { .. }
+                | TaskType::BranchOnCard { .. }
+        )
+    }
+    //a BranchOnCard picks the task of that card, false if it has none for it
+    pub fn choose(&mut self, card: u32) -> bool {
+        let task = match &self.what {
+            TaskType::BranchOnCard { cases, otherwise } => cases
+                .iter()
+                .find(|(c, _)| *c == card)
+                .map(|(_, t)| t)
+                .or(otherwise.as_deref()),
+            _ => None,
+        };
+        match task {
+            Some(task) => {
+                self.children = vec![TaskQueue::new(&self.group, vec![task.clone()])];
+                true
+            }
+            None => false,
         }
     }
-    pub fn new_single_move(x0: f32, y0: f32, d: f32) -> Self {
-        ToioTask {
-            what: TaskType::SingleMovement,
-            duration: None,
-            targets: None,
-            target: Some(vec2(x0, y0)),
-            target_angle: None,
-            target_angles: None,
-            power: None,
-            distance: Some(d),
-            start_time: 0,
+    //run the task on another group of cubes
+    pub fn on(mut self, group: &str) -> Self {
+        self.group = group.to_string();
+        self
+    }
+    //fail when not done after t ms
+    pub fn timeout(mut self, t: u64) -> Self {
+        self.timeout = Some(t);
+        self
+    }
+    pub fn on_failure(mut self, policy: OnFailure) -> Self {
+        self.on_failure = policy;
+        self
+    }
+    //fail when a cube of the group notices that motion, on_failure says what's next
+    pub fn abort_on(mut self, motion: MotionEvent) -> Self {
+        self.abort_on.push(motion);
+        self
+    }
+    //has the task been running for longer than its timeout?
+    pub fn timed_out(&self, now: u64) -> bool {
+        match self.timeout {
+            Some(t) => now > self.start_time + t,
+            None => false,
         }
     }
+    pub fn new_pair_move(x0: f32, y0: f32, x1: f32, y1: f32, d: f32) -> Self {
+        ToioTask::new_group_move(vec![vec2(x0, y0), vec2(x1, y1)], d)
+    }
+    pub fn new_group_move(targets: Vec<Vector2>, d: f32) -> Self {
+        ToioTask::new(TaskType::PairMovement {
+            targets,
+            distance: d,
+        })
+    }
+    pub fn new_pair_move_shift(x0: f32, y0: f32, d: f32) -> Self {
+        ToioTask::new(TaskType::PairMovementShift {
+            target: vec2(x0, y0),
+            distance: d,
+        })
+    }
+    pub fn new_single_move(x0: f32, y0: f32, d: f32) -> Self {
+        ToioTask::new(TaskType::SingleMovement {
+            target: vec2(x0, y0),
+            distance: d,
+        })
+    }
     pub fn new_wait(t: u64) -> Self {
-        ToioTask {
-            what: TaskType::Wait,
-            duration: Some(t),
-            targets: None,
-            target: None,
-            target_angle: None,
-            target_angles: None,
-            power: None,
-            distance: None,
-            start_time: 0,
-        }
+        ToioTask::new(TaskType::Wait { duration: t })
+    }
+    pub fn new_move_to(targets: Vec<Waypoint>, config: TargetConfig) -> Self {
+        ToioTask::new(TaskType::MoveTo { targets, config })
+    }
+    pub fn new_follow_path(paths: Vec<Vec<Waypoint>>, config: TargetConfig) -> Self {
+        ToioTask::new(TaskType::FollowPath { paths, config })
     }
     pub fn new_wiggle(t: u64, power: f32) -> Self {
-        ToioTask {
-            what: TaskType::Wiggle,
-            duration: Some(t),
-            targets: None,
-            target: None,
-            target_angle: None,
-            target_angles: None,
-            power: Some(power),
-            distance: None,
-            start_time: 0,
-        }
+        ToioTask::new(TaskType::Wiggle { duration: t, power })
     }
     pub fn new_spin(t: u64, power: f32) -> Self {
-        ToioTask {
-            what: TaskType::Spin,
-            duration: Some(t),
-            targets: None,
-            target: None,
-            target_angle: None,
-            target_angles: None,
-            power: Some(power),
-            distance: None,
-            start_time: 0,
-        }
+        ToioTask::new(TaskType::Spin { duration: t, power })
     }
     pub fn new_get_close(d: f32) -> Self {
-        ToioTask {
-            what: TaskType::GetClose,
-            duration: None,
-            targets: None,
-            target: None,
-            target_angle: None,
-            target_angles: None,
-            power: None,
-            distance: Some(d),
-            start_time: 0,
-        }
+        ToioTask::new(TaskType::GetClose { distance: d })
     }
     pub fn new_get_away(d: f32) -> Self {
-        ToioTask {
-            what: TaskType::GetAway,
-            duration: None,
-            targets: None,
-            target: None,
-            target_angle: None,
-            target_angles: None,
-            power: None,
-            distance: Some(d),
-            start_time: 0,
-        }
+        ToioTask::new(TaskType::GetAway { distance: d })
     }
     pub fn new_target_angle(a0: f32, d: f32) -> Self {
-        ToioTask {
-            what: TaskType::TargetAngle,
-            duration: None,
-            targets: None,
-            target: None,
-            target_angle: Some(a0),
-            target_angles: None,
-            power: None,
-            distance: Some(d),
-            start_time: 0,
-        }
+        ToioTask::new(TaskType::TargetAngle {
+            angle: a0,
+            tolerance: d,
+        })
     }
     pub fn new_target_angles(a0: f32, a1: f32, d: f32) -> Self {
-        ToioTask {
-            what: TaskType::TargetAngles,
-            duration: None,
-            targets: None,
-            target: None,
-            target_angle: None,
-            target_angles: Some((a0, a1)),
-            power: None,
-            distance: Some(d),
-            start_time: 0,
-        }
+        ToioTask::new_group_target_angles(vec![a0, a1], d)
     }
-    pub fn start(&mut self, now: u64) {
-        self.start_time = now;
+    pub fn new_group_target_angles(angles: Vec<f32>, d: f32) -> Self {
+        ToioTask::new(TaskType::TargetAngles {
+            angles,
+            tolerance: d,
+        })
+    }
+    pub fn new_sync(name: &str) -> Self {
+        ToioTask::new(TaskType::Sync {
+            name: name.to_string(),
+        })
+    }
+    pub fn new_wait_button(cube: Option<usize>) -> Self {
+        ToioTask::new(TaskType::WaitButton { cube })
+    }
+    pub fn new_wait_card(card: Option<u32>, cube: Option<usize>) -> Self {
+        ToioTask::new(TaskType::WaitCard { card, cube })
+    }
+    pub fn new_wait_motion(motion: MotionEvent, cube: Option<usize>) -> Self {
+        ToioTask::new(TaskType::WaitMotion { motion, cube })
+    }
+    pub fn new_branch_on_card(cases: Vec<(u32, ToioTask)>, otherwise: Option<ToioTask>) -> Self {
+        ToioTask::new(TaskType::BranchOnCard {
+            cases,
+            otherwise: otherwise.map(Box::new),
+        })
+    }
+    pub fn new_set_light(r: u8, g: u8, b: u8, duration: i32) -> Self {
+        ToioTask::new(TaskType::SetLight {
+            light: LightStep::new(r, g, b, duration),
+        })
+    }
+    pub fn new_light_sequence(steps: Vec<LightStep>, repeat: Option<u32>) -> Self {
+        ToioTask::new(TaskType::LightSequence { steps, repeat })
     }
-    pub fn is_done(&self, now: u64, data: Vec<(f32, f32, f32)>) -> bool {
-        //x0:f32, y0:f32, angle0:f32,x1:f32, y1:f32, angle1:f32, x2:f32, y2) -> bool {
-        let (mut x0, mut y0, mut angle0) = data[0];
-        let (mut x1, mut y1, mut angle1) = data[0];
-        let (mut x2, mut y2, mut angle2) = data[0];
-        if data.len() > 1 {
-            x1 = data[1].0;
-            y1 = data[1].1;
-            angle1 = data[1].2;
+    pub fn new_play_sound(effect: u8, volume: u8) -> Self {
+        ToioTask::new(TaskType::PlaySound { effect, volume })
+    }
+    pub fn new_play_notes(notes: Vec<Note>, repeat: Option<u32>) -> Self {
+        ToioTask::new(TaskType::PlayNotes { notes, repeat })
+    }
+    pub fn new_custom<T: Task + Clone + 'static>(task: T) -> Self {
+        ToioTask::new(TaskType::Custom(Box::new(task)))
+    }
+    pub fn new_sequence(tasks: Vec<ToioTask>) -> Self {
+        ToioTask::new(TaskType::Sequence(tasks))
+    }
+    pub fn new_parallel(tasks: Vec<ToioTask>, join: Join) -> Self {
+        ToioTask::new(TaskType::Parallel { tasks, join })
+    }
+    pub fn new_repeat(task: ToioTask, times: u32) -> Self {
+        ToioTask::new(TaskType::Repeat {
+            task: Box::new(task),
+            times: Some(times),
+        })
+    }
+    pub fn new_forever(task: ToioTask) -> Self {
+        ToioTask::new(TaskType::Repeat {
+            task: Box::new(task),
+            times: None,
+        })
+    }
+    pub fn new_loop_until(task: ToioTask, until: ToioTask) -> Self {
+        ToioTask::new(TaskType::LoopUntil {
+            task: Box::new(task),
+            until: Box::new(until),
+        })
+    }
+    //the target command for this cube never got to it, send them all again
+    pub(crate) fn unsent(&mut self, id: CubeId) {
+        if self.requests.iter().any(|(cube, _)| *cube == id) {
+            self.requests.clear();
+        }
+        for branch in self.children.iter_mut() {
+            if let Some(task) = branch.current_mut() {
+                task.unsent(id);
+            }
         }
-        if data.len() > 2 {
-            x2 = data[2].0;
-            y2 = data[2].1;
-            angle2 = data[2].2;
+    }
+    //the name of the sync point if this task is one
+    pub fn sync_name(&self) -> Option<&str> {
+        match &self.what {
+            TaskType::Sync { name } => Some(name),
+            _ => None,
         }
+    }
+}
 
-        match self.what {
-            //PensUp, //>=102
-            //PensDown, //<=98
-            TaskType::Wiggle | TaskType::Spin | TaskType::Wait => {
-                let time_needed = self.duration.unwrap();
-                now > self.start_time + time_needed
+impl Task for ToioTask {
+    fn start(&mut self, now: u64) {
+        self.start_time = now;
+        self.iterations = 0;
+        self.requests.clear();
+        self.reset_children();
+        match &mut self.what {
+            TaskType::Custom(task) => task.start(now),
+            TaskType::LoopUntil { until, .. } => until.start(now),
+            _ => {}
+        }
+    }
+    fn step(
+        &mut self,
+        now: u64,
+        data: &[Pose],
+        shifts: &mut Vec<Vector2>,
+    ) -> Vec<Option<[i32; 2]>> {
+        let mut speeds = vec![None; data.len()];
+        if data.len() < self.cubes_needed() {
+            return speeds;
+        }
+        let c = center(data);
+        match &mut self.what {
+            TaskType::Spin { power, .. } => {
+                let s = (*power * 100.0).round() as i32;
+                for what in speeds.iter_mut() {
+                    *what = Some([s, -s]);
+                }
             }
-            TaskType::PairMovement => {
-                let d_wanted = self.distance.unwrap();
-                let (t0, t1) = self.targets.unwrap();
-                //are we far away from our targets?
-                let d0 = (t0 - vec2(x0, y0)).magnitude();
-                let d1 = (t1 - vec2(x1, y1)).magnitude();
-                d0.max(d1) < d_wanted
+            TaskType::TargetAngles { angles, .. } => {
+                for (i, (&(x, y, angle), t)) in data.iter().zip(angles.iter()).enumerate() {
+                    speeds[i] = Some(aim_angle(x, y, angle, *t));
+                }
             }
-            TaskType::PairMovementShift => {
-                let d_wanted = self.distance.unwrap();
-                let t0 = self.target.unwrap();
-                //are we far away from our targets?
-                let d0 = (t0 - vec2(x0 + x1, y0 + y1) * 0.5).magnitude();
-                d0 < d_wanted
+            TaskType::Wiggle { power, .. } => {
+                //wiggle around the center, every other cube in opposite phase
+                let wiggle_time = now.saturating_sub(self.start_time);
+                let phase = (wiggle_time as f32 / 1000.0 * 3.0 * TAU).cos();
+                for (i, &(x, y, angle)) in data.iter().enumerate() {
+                    let angle_to_center = (c.y - y).atan2(c.x - x);
+                    let sign = if i % 2 == 0 { 1.0 } else { -1.0 };
+                    speeds[i] = Some(aim_angle(
+                        x,
+                        y,
+                        angle,
+                        angle_to_center + sign * phase * *power,
+                    ));
+                }
             }
-            TaskType::SingleMovement => {
-                let d_wanted = self.distance.unwrap();
-                let t0 = self.target.unwrap();
-                //are we far away from our targets?
-                let d0 = (t0 - vec2(x0, y0)).magnitude();
-                d0 < d_wanted
-            }
-            TaskType::GetClose => {
-                let d = vec2(x1 - x0, y1 - y0).magnitude();
-                let d_wanted = self.distance.unwrap();
-                d < d_wanted
-            }
-            TaskType::GetAway => {
-                let d = vec2(x1 - x0, y1 - y0).magnitude();
-                let d_wanted = self.distance.unwrap();
-                d > d_wanted
-            }
-            TaskType::TargetAngle => {
-                let t0 = self.target_angle.unwrap();
-                let mut dangle = angle0 - t0;
-                while dangle < -PI {
-                    dangle += TAU;
+            TaskType::PairMovement { targets, .. } => {
+                for (i, (&(x, y, angle), t)) in data.iter().zip(targets.iter()).enumerate() {
+                    speeds[i] = Some(aimany(x, y, angle, t.x, t.y));
                 }
-                while dangle > PI {
-                    dangle -= TAU;
+            }
+            TaskType::SingleMovement { target, .. } => {
+                let (x0, y0, angle0) = data[0];
+                speeds[0] = Some(aimany(x0, y0, angle0, target.x, target.y));
+            }
+            TaskType::PairMovementShift { target, .. } => {
+                for (i, &(x, y, angle)) in data.iter().enumerate() {
+                    let shift = shifts.get(i).cloned().unwrap_or_else(|| vec2(0.0, 0.0));
+                    speeds[i] = Some(aimany(x, y, angle, target.x + shift.x, target.y + shift.y));
                 }
-                let d_wanted = self.distance.unwrap();
-                dangle.abs() < d_wanted
-            }
-            TaskType::TargetAngles => {
-                let (t0, t1) = self.target_angles.unwrap();
-                let mut dangle0 = angle0 - t0;
-                while dangle0 < -PI {
-                    dangle0 += TAU;
+            }
+            TaskType::GetClose { .. } => {
+                for (i, &(x, y, angle)) in data.iter().enumerate() {
+                    let other = center_of_others(data, i);
+                    speeds[i] = Some(aimany(x, y, angle, other.x, other.y));
                 }
-                while dangle0 > PI {
-                    dangle0 -= TAU;
+                *shifts = group_shifts(data);
+            }
+            TaskType::GetAway { .. } => {
+                for (i, &(x, y, angle)) in data.iter().enumerate() {
+                    //where to target
+                    let other = center_of_others(data, i);
+                    let angle_away = (other.y - y).atan2(other.x - x);
+                    speeds[i] = Some(aimany(
+                        x,
+                        y,
+                        angle,
+                        x - angle_away.cos() * DIST_AWAY,
+                        y - angle_away.sin() * DIST_AWAY,
+                    ));
                 }
-                let mut dangle1 = angle1 - t1;
-                while dangle1 < -PI {
-                    dangle1 += TAU;
+                *shifts = group_shifts(data);
+            }
+            TaskType::TargetAngle { angle: target, .. } => {
+                for (i, &(x, y, angle)) in data.iter().enumerate() {
+                    speeds[i] = Some(aim_angle(x, y, angle, *target));
                 }
-                while dangle1 > PI {
-                    dangle1 -= TAU;
+            }
+            TaskType::Wait { .. } => {
+                //keep still until the time is up
+                for what in speeds.iter_mut() {
+                    *what = Some([0, 0]);
+                }
+            }
+            //the cubes drive themselves, the engine sends them their targets
+            TaskType::MoveTo { .. } | TaskType::FollowPath { .. } => {}
+            //released by release_syncs, nothing to do meanwhile
+            TaskType::Sync { .. } => {}
+            //the engine knows about the presses
+            TaskType::WaitButton { .. }
+            | TaskType::WaitCard { .. }
+            | TaskType::WaitMotion { .. } => {}
+            //the engine sends them
+            TaskType::SetLight { .. }
+            | TaskType::LightSequence { .. }
+            | TaskType::PlaySound { .. }
+            | TaskType::PlayNotes { .. } => {}
+            TaskType::Custom(task) => return task.step(now, data, shifts),
+            //their children have their own groups, the engine runs them
+            TaskType::Sequence(_)
+            | TaskType::Parallel { .. }
+            | TaskType::Repeat { .. }
+            | TaskType::LoopUntil { .. }
+            | TaskType::BranchOnCard { .. } => {}
+        }
+        speeds
+    }
+    //a task is never done when the group misses some cubes
+    fn is_done(&self, now: u64, data: &[Pose]) -> bool {
+        if data.len() < self.cubes_needed() {
+            return false;
+        }
+        match &self.what {
+            //PensUp, //>=102
+            //PensDown, //<=98
+            TaskType::Wiggle { duration, .. }
+            | TaskType::Spin { duration, .. }
+            | TaskType::Wait { duration } => now > self.start_time + duration,
+            TaskType::PairMovement { targets, distance } => {
+                //are we far away from our targets?
+                data.iter()
+                    .zip(targets.iter())
+                    .map(|(&(x, y, _), t)| (*t - vec2(x, y)).magnitude())
+                    .fold(0.0, f32::max)
+                    < *distance
+            }
+            TaskType::PairMovementShift { target, distance } => {
+                //are we far away from our targets?
+                let d0 = (*target - center(data)).magnitude();
+                d0 < *distance
+            }
+            TaskType::SingleMovement { target, distance } => {
+                let (x0, y0, _) = data[0];
+                //are we far away from our targets?
+                let d0 = (*target - vec2(x0, y0)).magnitude();
+                d0 < *distance
+            }
+            TaskType::GetClose { distance } => {
+                //for a pair this is the distance between the two cubes
+                let d = data
+                    .iter()
+                    .map(|&(x, y, _)| (vec2(x, y) - center(data)).magnitude() * 2.0)
+                    .fold(0.0, f32::max);
+                d < *distance
+            }
+            TaskType::GetAway { distance } => min_distance(data) > *distance,
+            TaskType::TargetAngle { angle, tolerance } => {
+                data.iter()
+                    .map(|&(_, _, a)| angle_between(a, *angle).abs())
+                    .fold(0.0, f32::max)
+                    < *tolerance
+            }
+            TaskType::TargetAngles { angles, tolerance } => {
+                data.iter()
+                    .zip(angles.iter())
+                    .map(|(&(_, _, a), t)| angle_between(a, *t).abs())
+                    .fold(0.0, f32::max)
+                    < *tolerance
+            }
+            //the engine waits for the answers of the cubes
+            TaskType::MoveTo { .. } | TaskType::FollowPath { .. } => false,
+            //released by release_syncs, not by the cubes
+            TaskType::Sync { .. } => false,
+            TaskType::WaitButton { .. }
+            | TaskType::WaitCard { .. }
+            | TaskType::WaitMotion { .. } => false,
+            //nothing left to do once sent
+            TaskType::SetLight { .. }
+            | TaskType::LightSequence { .. }
+            | TaskType::PlaySound { .. }
+            | TaskType::PlayNotes { .. } => true,
+            //once a card picked a task, when it is done
+            TaskType::BranchOnCard { .. } => {
+                !self.children.is_empty() && self.children.iter().all(|q| q.is_empty())
+            }
+            TaskType::Sequence(_) => self.children.iter().all(|q| q.is_empty()),
+            TaskType::Parallel { join, .. } => match join {
+                Join::All => self.children.iter().all(|q| q.is_empty()),
+                Join::Any => self.children.is_empty() || self.children.iter().any(|q| q.is_empty()),
+            },
+            TaskType::Repeat { times, .. } => times.is_some_and(|n| self.iterations >= n),
+            //the engine gives until the poses of its own group
+            TaskType::LoopUntil { until, .. } => until.is_done(now, data),
+            TaskType::Custom(task) => task.is_done(now, data),
+        }
+    }
+    fn cubes_needed(&self) -> usize {
+        match &self.what {
+            TaskType::Wiggle { .. }
+            | TaskType::Spin { .. }
+            | TaskType::Wait { .. }
+            | TaskType::Sync { .. }
+            | TaskType::SetLight { .. }
+            | TaskType::LightSequence { .. }
+            | TaskType::PlaySound { .. }
+            | TaskType::PlayNotes { .. } => 0,
+            //up to the cube they wait on
+            TaskType::WaitButton { cube }
+            | TaskType::WaitCard { cube, .. }
+            | TaskType::WaitMotion { cube, .. } => cube.map_or(0, |c| c + 1),
+            TaskType::SingleMovement { .. } | TaskType::TargetAngle { .. } => 1,
+            TaskType::PairMovementShift { .. }
+            | TaskType::GetClose { .. }
+            | TaskType::GetAway { .. } => 2,
+            TaskType::PairMovement { targets, .. } => targets.len(),
+            TaskType::MoveTo { targets, .. } => targets.len(),
+            TaskType::FollowPath { paths, .. } => paths.len(),
+            TaskType::TargetAngles { angles, .. } => angles.len(),
+            TaskType::Custom(task) => task.cubes_needed(),
+            TaskType::Sequence(_)
+            | TaskType::Parallel { .. }
+            | TaskType::Repeat { .. }
+            | TaskType::LoopUntil { .. }
+            | TaskType::BranchOnCard { .. } => 0,
+        }
+    }
+    fn delay(&mut self, ms: u64) {
+        self.start_time += ms;
+        //the cubes were stopped meanwhile, they need their targets again
+        self.requests.clear();
+        for branch in self.children.iter_mut() {
+            if let Some(task) = branch.current_mut() {
+                task.delay(ms);
+            }
+        }
+        match &mut self.what {
+            TaskType::Custom(task) => task.delay(ms),
+            TaskType::LoopUntil { until, .. } => until.delay(ms),
+            _ => {}
+        }
+    }
+}
+
+//what happens to a running queue when its tasks are replaced
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReloadMode {
+    //start the new list from its first task
+    Restart,
+    //skip as many tasks of the new list as the queue already finished
+    KeepStep,
+}
+
+#[derive(Clone)]
+pub struct TaskQueue {
+    pub name: String,
+    pub tasks: Vec<ToioTask>,
+    started: bool,
+    //the list as it was given, to start over
+    list: Vec<ToioTask>,
+    //when the queue was paused, or when its current task started if that's later
+    paused_at: Option<u64>,
+    //same for a hold, while a cube it needs is missing, apart from the pause
+    held_at: Option<u64>,
+    //pause again once the current task is done
+    stepping: bool,
+    //tasks of the list finished so far
+    step: usize,
+    //tasks put in front by interrupt, not part of the list
+    interrupts: usize,
+}
+
+impl TaskQueue {
+    pub fn new(name: &str, tasks: Vec<ToioTask>) -> Self {
+        TaskQueue {
+            name: name.to_string(),
+            list: tasks.clone(),
+            tasks,
+            started: false,
+            paused_at: None,
+            held_at: None,
+            stepping: false,
+            step: 0,
+            interrupts: 0,
+        }
+    }
+    pub fn current(&self) -> Option<&ToioTask> {
+        self.tasks.first()
+    }
+    pub fn current_mut(&mut self) -> Option<&mut ToioTask> {
+        self.tasks.first_mut()
+    }
+    //index of the current task in the list the queue was given
+    pub fn step(&self) -> usize {
+        self.step
+    }
+    //start the current task if nobody did yet
+    pub fn start(&mut self, now: u64) {
+        if !self.started {
+            if let Some(task) = self.tasks.first_mut() {
+                task.start(now);
+                self.started = true;
+                //nothing of this task happened before the pause
+                for at in self.paused_at.iter_mut().chain(self.held_at.iter_mut()) {
+                    *at = now;
                 }
-                let d_wanted = self.distance.unwrap();
-                dangle0.abs().max(dangle1.abs()) < d_wanted
             }
-            _ => true,
         }
     }
+    //drop the current task and start the next one
+    pub fn next(&mut self, now: u64) {
+        if !self.tasks.is_empty() {
+            self.tasks.remove(0);
+            if self.interrupts > 0 {
+                self.interrupts -= 1;
+            } else {
+                self.step += 1;
+            }
+        }
+        self.started = false;
+        self.start(now);
+        if self.stepping {
+            self.stepping = false;
+            self.pause(now);
+        }
+    }
+    //put a task in front of the current one and start it
+    pub fn interrupt(&mut self, task: ToioTask, now: u64) {
+        self.tasks.insert(0, task);
+        self.interrupts += 1;
+        self.started = false;
+        self.start(now);
+    }
+    //put several tasks in front of the current one and start the first
+    pub fn interrupt_with(&mut self, tasks: Vec<ToioTask>, now: u64) {
+        self.interrupts += tasks.len();
+        self.tasks.splice(0..0, tasks);
+        self.started = false;
+        self.start(now);
+    }
+    //start the current task over
+    pub fn restart(&mut self, now: u64) {
+        self.started = false;
+        self.start(now);
+    }
+    //deal with the current task failing, the way its on_failure says
+    pub fn fail(&mut self, now: u64) -> FailureAction {
+        let task = match self.tasks.first_mut() {
+            Some(task) => task,
+            None => return FailureAction::Skipped,
+        };
+        match &mut task.on_failure {
+            OnFailure::Retry(n) if task.retries < *n => {
+                task.retries += 1;
+                let retries = task.retries;
+                self.restart(now);
+                FailureAction::Retried(retries)
+            }
+            OnFailure::Skip | OnFailure::Retry(_) => {
+                self.next(now);
+                FailureAction::Skipped
+            }
+            OnFailure::AbortQueue => {
+                self.clear();
+                FailureAction::Aborted
+            }
+            OnFailure::Recover(recovery) => {
+                let recovery = std::mem::take(recovery);
+                self.next(now);
+                self.interrupt_with(recovery, now);
+                FailureAction::Recovering
+            }
+        }
+    }
+    //swap in a new task list, dropping pending interrupts
+    pub fn replace(&mut self, tasks: Vec<ToioTask>, mode: ReloadMode, now: u64) {
+        self.list = tasks.clone();
+        self.tasks = tasks;
+        self.interrupts = 0;
+        match mode {
+            ReloadMode::Restart => self.step = 0,
+            ReloadMode::KeepStep => {
+                self.step = self.step.min(self.tasks.len());
+                self.tasks.drain(..self.step);
+            }
+        }
+        self.started = false;
+        self.start(now);
+    }
+    pub fn clear(&mut self) {
+        self.tasks.clear();
+        self.interrupts = 0;
+        self.started = false;
+    }
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+    //start the list over from its first task
+    pub fn rewind(&mut self, now: u64) {
+        self.replace(self.list.clone(), ReloadMode::Restart, now);
+    }
+    //the engine holds the cubes of a paused queue and its task's time stands still
+    pub fn pause(&mut self, now: u64) {
+        if self.paused_at.is_none() {
+            self.paused_at = Some(now);
+        }
+    }
+    pub fn resume(&mut self, now: u64) {
+        if let Some(at) = self.paused_at.take() {
+            self.stopped_for(at, now);
+        }
+    }
+    //the engine holds a queue while a cube it needs is missing, a pause stays as it was
+    pub fn hold(&mut self, now: u64) {
+        if self.held_at.is_none() {
+            self.held_at = Some(now);
+        }
+    }
+    pub fn release(&mut self, now: u64) {
+        if let Some(at) = self.held_at.take() {
+            self.stopped_for(at, now);
+        }
+    }
+    //a pause or a hold that began at that time just ended, delay the task by the time
+    //it stood still, counting once what the other one still covers
+    fn stopped_for(&mut self, at: u64, now: u64) {
+        let other = self.paused_at.or(self.held_at);
+        let since = other.map_or(at, |other| other.min(at));
+        if let Some(task) = self.tasks.first_mut() {
+            task.delay(now.saturating_sub(since));
+        }
+        for other in self.paused_at.iter_mut().chain(self.held_at.iter_mut()) {
+            *other = now;
+        }
+    }
+    //run the current task to its end, then pause again
+    pub fn step_through(&mut self, now: u64) {
+        self.stepping = true;
+        self.resume(now);
+    }
+    pub fn is_paused(&self) -> bool {
+        self.paused_at.is_some()
+    }
+    pub fn is_held(&self) -> bool {
+        self.held_at.is_some()
+    }
+    //the sync point we are waiting at, if any
+    pub fn waiting_at(&self) -> Option<&str> {
+        self.current().and_then(|t| t.sync_name())
+    }
+    //is this sync point still ahead of us?
+    fn expects(&self, name: &str) -> bool {
+        self.tasks.iter().any(|t| t.sync_name() == Some(name))
+    }
+}
+
+//move past every sync point all the queues that use it have reached
+//a paused or held queue isn't there yet, and stays where it is
+pub fn release_syncs(queues: &mut [TaskQueue], now: u64) {
+    let there =
+        |q: &TaskQueue, name: &str| q.waiting_at() == Some(name) && !q.is_paused() && !q.is_held();
+    let mut ready = Vec::new();
+    for queue in queues.iter() {
+        if let Some(name) = queue.waiting_at() {
+            let everyone_there = queues.iter().all(|q| !q.expects(name) || there(q, name));
+            if everyone_there && !ready.iter().any(|r| r == name) {
+                ready.push(name.to_string());
+            }
+        }
+    }
+    for queue in queues.iter_mut() {
+        let release = match queue.waiting_at() {
+            Some(name) => ready.iter().any(|r| r == name) && there(queue, name),
+            None => false,
+        };
+        if release {
+            queue.next(now);
+        }
+    }
+}
+
+//difference between two angles, in -PI..PI
+pub fn angle_between(angle: f32, target: f32) -> f32 {
+    let mut dangle = angle - target;
+    while dangle < -PI {
+        dangle += TAU;
+    }
+    while dangle > PI {
+        dangle -= TAU;
+    }
+    dangle
+}
+
+//center of the group
+pub fn center(data: &[Pose]) -> Vector2 {
+    let sum = data
+        .iter()
+        .fold(vec2(0.0, 0.0), |sum, &(x, y, _)| sum + vec2(x, y));
+    sum / data.len().max(1) as f32
+}
+
+//center of every cube of the group but cube i, for a pair this is the other cube
+pub fn center_of_others(data: &[Pose], i: usize) -> Vector2 {
+    if data.len() < 2 {
+        let (x, y, _) = data[i];
+        return vec2(x, y);
+    }
+    let (x, y, _) = data[i];
+    (center(data) * data.len() as f32 - vec2(x, y)) / (data.len() - 1) as f32
+}
+
+//where each cube sits relative to the center of its group
+pub fn group_shifts(data: &[Pose]) -> Vec<Vector2> {
+    let c = center(data);
+    data.iter().map(|&(x, y, _)| vec2(x, y) - c).collect()
+}
+
+//smallest distance between two cubes of the group
+pub fn min_distance(data: &[Pose]) -> f32 {
+    let mut d = f32::MAX;
+    for (i, &(x0, y0, _)) in data.iter().enumerate() {
+        for &(x1, y1, _) in data.iter().skip(i + 1) {
+            d = d.min(vec2(x1 - x0, y1 - y0).magnitude());
+        }
+    }
+    d
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn started(mut task: ToioTask, now: u64) -> ToioTask {
+        task.start(now);
+        task
+    }
+
+    #[test]
+    fn timed_tasks() {
+        for task in [
+            ToioTask::new_wait(1000),
+            ToioTask::new_wiggle(1000, 0.5),
+            ToioTask::new_spin(1000, 0.2),
+        ] {
+            let task = started(task, 500);
+            assert!(!task.is_done(500, &[]));
+            assert!(!task.is_done(1500, &[]));
+            assert!(task.is_done(1501, &[]));
+        }
+    }
+
+    #[test]
+    fn pair_movement() {
+        let task = ToioTask::new_pair_move(-50.0, 0.0, 50.0, 0.0, 10.0);
+        assert!(task.is_done(0, &[(-45.0, 5.0, 0.0), (55.0, 0.0, 1.0)]));
+        //each cube has its own target
+        assert!(!task.is_done(0, &[(50.0, 0.0, 0.0), (-50.0, 0.0, 0.0)]));
+        assert!(!task.is_done(0, &[(-50.0, 0.0, 0.0), (50.0, 12.0, 0.0)]));
+    }
+
+    #[test]
+    fn group_movement() {
+        let targets = vec![vec2(0.0, 0.0), vec2(50.0, 0.0), vec2(100.0, 0.0)];
+        let task = ToioTask::new_group_move(targets, 5.0);
+        let data = [(0.0, 0.0, 0.0), (50.0, 0.0, 0.0), (100.0, 0.0, 0.0)];
+        assert!(task.is_done(0, &data));
+        assert!(!task.is_done(0, &data[..2]));
+    }
+
+    #[test]
+    fn pair_movement_shift() {
+        let task = ToioTask::new_pair_move_shift(10.0, 10.0, 15.0);
+        //the center of the pair counts, not the cubes
+        assert!(task.is_done(0, &[(-20.0, 10.0, 0.0), (40.0, 10.0, 0.0)]));
+        assert!(!task.is_done(0, &[(10.0, 10.0, 0.0), (80.0, 10.0, 0.0)]));
+    }
+
+    #[test]
+    fn single_movement() {
+        let task = ToioTask::new_single_move(100.0, -100.0, 10.0);
+        assert!(task.is_done(0, &[(95.0, -95.0, 0.0)]));
+        assert!(!task.is_done(0, &[(80.0, -100.0, 0.0)]));
+        //only the first cube matters
+        assert!(task.is_done(0, &[(100.0, -100.0, 0.0), (0.0, 0.0, 0.0)]));
+    }
+
+    #[test]
+    fn get_close() {
+        let task = ToioTask::new_get_close(DIST_CLOSE);
+        assert!(task.is_done(0, &[(0.0, 0.0, 0.0), (50.0, 0.0, 0.0)]));
+        assert!(!task.is_done(0, &[(0.0, 0.0, 0.0), (0.0, 60.0, 0.0)]));
+    }
+
+    #[test]
+    fn get_away() {
+        let task = ToioTask::new_get_away(DIST_AWAY);
+        assert!(task.is_done(0, &[(0.0, 0.0, 0.0), (60.0, 0.0, 0.0)]));
+        assert!(!task.is_done(0, &[(0.0, 0.0, 0.0), (0.0, 50.0, 0.0)]));
+        //every pair of the group has to be far enough
+        let data = [(0.0, 0.0, 0.0), (100.0, 0.0, 0.0), (0.0, 30.0, 0.0)];
+        assert!(!task.is_done(0, &data));
+    }
+
+    #[test]
+    fn target_angle() {
+        let task = ToioTask::new_target_angle(PI * 0.5, 0.1);
+        assert!(task.is_done(0, &[(0.0, 0.0, PI * 0.5 + 0.05)]));
+        assert!(!task.is_done(0, &[(0.0, 0.0, 0.0)]));
+        //angles wrap around
+        let task = ToioTask::new_target_angle(PI - 0.02, 0.1);
+        assert!(task.is_done(0, &[(0.0, 0.0, -PI + 0.02)]));
+        assert!(task.is_done(0, &[(0.0, 0.0, PI + TAU)]));
+    }
+
+    #[test]
+    fn target_angles() {
+        let task = ToioTask::new_target_angles(0.0, PI, 0.1);
+        assert!(task.is_done(0, &[(0.0, 0.0, 0.05), (0.0, 0.0, -PI)]));
+        assert!(!task.is_done(0, &[(0.0, 0.0, PI), (0.0, 0.0, 0.0)]));
+    }
+
+    #[test]
+    fn missing_cubes_are_never_done() {
+        let one = [(0.0, 0.0, 0.0)];
+        assert!(!ToioTask::new_get_close(DIST_CLOSE).is_done(0, &one));
+        assert!(!ToioTask::new_get_away(DIST_AWAY).is_done(0, &one));
+        assert!(!ToioTask::new_pair_move(0.0, 0.0, 0.0, 0.0, 10.0).is_done(0, &one));
+        assert!(!ToioTask::new_pair_move_shift(0.0, 0.0, 10.0).is_done(0, &one));
+        assert!(!ToioTask::new_target_angles(0.0, 0.0, 1.0).is_done(0, &one));
+        assert!(!ToioTask::new_single_move(0.0, 0.0, 10.0).is_done(0, &[]));
+        assert!(!ToioTask::new_target_angle(0.0, 1.0).is_done(0, &[]));
+    }
+
+    #[test]
+    fn sync_is_released_by_the_queues() {
+        assert!(!ToioTask::new_sync("a").is_done(u64::MAX, &[]));
+    }
+
+    #[derive(Clone)]
+    struct Blink {
+        started: u64,
+    }
+
+    impl Task for Blink {
+        fn start(&mut self, now: u64) {
+            self.started = now;
+        }
+        fn step(&mut self, now: u64, data: &[Pose], _: &mut Vec<Vector2>) -> Vec<Option<[i32; 2]>> {
+            let s = if (now - self.started) % 200 < 100 {
+                20
+            } else {
+                -20
+            };
+            vec![Some([s, s]); data.len()]
+        }
+        fn is_done(&self, now: u64, _: &[Pose]) -> bool {
+            now >= self.started + 1000
+        }
+        fn cubes_needed(&self) -> usize {
+            1
+        }
+    }
+
+    #[test]
+    fn custom_tasks() {
+        let mut task = ToioTask::new_custom(Blink { started: 0 });
+        task.start(500);
+        let data = [(0.0, 0.0, 0.0)];
+        assert_eq!(task.step(550, &data, &mut vec![]), vec![Some([20, 20])]);
+        assert_eq!(task.step(650, &data, &mut vec![]), vec![Some([-20, -20])]);
+        assert!(!task.is_done(1000, &data));
+        assert!(task.is_done(1500, &data));
+        //the group still needs its cubes
+        assert!(!task.is_done(1500, &[]));
+        assert!(task.step(550, &[], &mut vec![]).is_empty());
+    }
+
+    #[test]
+    fn composites_start_fresh_copies() {
+        let mut task = ToioTask::new_repeat(ToioTask::new_sequence(waits(&[10, 20])), 2);
+        task.start(100);
+        assert_eq!(task.children.len(), 1);
+        task.children[0].start(100);
+        task.children[0].next(110);
+        assert!(!task.is_done(200, &[]));
+        task.next_iteration();
+        task.next_iteration();
+        assert!(task.is_done(200, &[]));
+        //starting it again goes back to the first iteration
+        task.start(300);
+        assert_eq!(task.iterations, 0);
+        assert_eq!(task.children[0].current().unwrap().children[0].step(), 0);
+
+        let mut any = ToioTask::new_parallel(waits(&[10, 20]), Join::Any);
+        let mut all = ToioTask::new_parallel(waits(&[10, 20]), Join::All);
+        for task in [&mut any, &mut all] {
+            task.start(0);
+            assert_eq!(task.children.len(), 2);
+            task.children[0].start(0);
+            task.children[0].next(10);
+        }
+        assert!(any.is_done(10, &[]));
+        assert!(!all.is_done(10, &[]));
+
+        let mut forever = ToioTask::new_forever(ToioTask::new_wait(10));
+        forever.start(0);
+        for _ in 0..100 {
+            forever.next_iteration();
+        }
+        assert!(!forever.is_done(0, &[]));
+
+        let mut until = ToioTask::new_loop_until(ToioTask::new_wait(10), ToioTask::new_wait(50));
+        until.start(0);
+        let data = [(0.0, 0.0, 0.0)];
+        assert!(!until.is_done(50, &data));
+        assert!(until.is_done(51, &data));
+    }
+
+    #[test]
+    fn paused_tasks_keep_their_time() {
+        let mut queue = TaskQueue::new("q", waits(&[100, 200]));
+        queue.start(0);
+        queue.pause(50);
+        queue.resume(1050);
+        let data = [(0.0, 0.0, 0.0)];
+        assert!(!queue.current().unwrap().is_done(1100, &data));
+        assert!(queue.current().unwrap().is_done(1101, &data));
+
+        //a task started while paused only counts from its start
+        queue.pause(1100);
+        queue.next(1200);
+        assert!(queue.is_paused());
+        queue.resume(1300);
+        assert_eq!(queue.current().unwrap().start_time, 1300);
+
+        //nested tasks are delayed too
+        let mut queue = TaskQueue::new("q", vec![ToioTask::new_sequence(waits(&[100]))]);
+        queue.start(0);
+        queue.current_mut().unwrap().children[0].start(0);
+        queue.pause(50);
+        queue.resume(150);
+        assert_eq!(
+            queue.current().unwrap().children[0]
+                .current()
+                .unwrap()
+                .start_time,
+            100
+        );
+    }
+
+    #[test]
+    fn holds_and_pauses_stay_apart() {
+        let mut queue = TaskQueue::new("q", waits(&[100]));
+        queue.start(0);
+        queue.hold(50);
+        queue.pause(100);
+        //the cube is back, the operator's pause stays
+        queue.release(200);
+        assert!(!queue.is_held());
+        assert!(queue.is_paused());
+        queue.resume(300);
+        //stood still from 50 to 300, counted once
+        assert_eq!(queue.current().unwrap().start_time, 250);
+
+        //and a resume doesn't release a hold
+        queue.hold(400);
+        queue.pause(450);
+        queue.resume(500);
+        assert!(queue.is_held());
+        queue.release(600);
+        assert_eq!(queue.current().unwrap().start_time, 450);
+    }
+
+    #[test]
+    fn stepping_and_rewinding() {
+        let mut queue = TaskQueue::new("q", waits(&[1, 2, 3]));
+        queue.start(0);
+        queue.pause(0);
+        queue.step_through(10);
+        assert!(!queue.is_paused());
+        queue.next(20);
+        assert!(queue.is_paused());
+        assert_eq!(duration(&queue), Some(2));
+
+        queue.next(30);
+        queue.rewind(40);
+        assert_eq!(queue.step(), 0);
+        assert_eq!(duration(&queue), Some(1));
+        assert_eq!(queue.current().unwrap().start_time, 40);
+    }
+
+    #[test]
+    fn queue_starts_tasks() {
+        let mut queue = TaskQueue::new("q", vec![ToioTask::new_wait(10), ToioTask::new_wait(10)]);
+        queue.start(100);
+        assert_eq!(queue.current().unwrap().start_time, 100);
+        //starting twice doesn't restart the task
+        queue.start(200);
+        assert_eq!(queue.current().unwrap().start_time, 100);
+        queue.next(300);
+        assert_eq!(queue.current().unwrap().start_time, 300);
+        queue.next(400);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn syncs_wait_for_every_queue() {
+        let mut queues = vec![
+            TaskQueue::new("a", vec![ToioTask::new_sync("s"), ToioTask::new_wait(10)]),
+            TaskQueue::new("b", vec![ToioTask::new_wait(10), ToioTask::new_sync("s")]),
+            TaskQueue::new("c", vec![ToioTask::new_wait(10)]),
+        ];
+        release_syncs(&mut queues, 0);
+        assert_eq!(queues[0].waiting_at(), Some("s"));
+        queues[1].next(10);
+        release_syncs(&mut queues, 10);
+        assert_eq!(queues[0].waiting_at(), None);
+        assert!(queues[1].is_empty());
+        //c doesn't use s and never waited
+        assert_eq!(queues[2].tasks.len(), 1);
+    }
+
+    #[test]
+    fn paused_queues_stay_at_their_sync() {
+        let mut queues = vec![
+            TaskQueue::new("a", vec![ToioTask::new_sync("s"), ToioTask::new_wait(10)]),
+            TaskQueue::new("b", vec![ToioTask::new_sync("s"), ToioTask::new_wait(10)]),
+        ];
+        queues[0].pause(0);
+        release_syncs(&mut queues, 0);
+        assert_eq!(queues[0].waiting_at(), Some("s"));
+        assert_eq!(queues[1].waiting_at(), Some("s"));
+        queues[0].resume(10);
+        queues[1].hold(10);
+        release_syncs(&mut queues, 10);
+        assert_eq!(queues[0].waiting_at(), Some("s"));
+        queues[1].release(20);
+        release_syncs(&mut queues, 20);
+        assert_eq!(queues[0].waiting_at(), None);
+        assert_eq!(queues[1].waiting_at(), None);
+    }
+
+    fn waits(durations: &[u64]) -> Vec<ToioTask> {
+        durations.iter().map(|&d| ToioTask::new_wait(d)).collect()
+    }
+
+    fn duration(queue: &TaskQueue) -> Option<u64> {
+        match queue.current()?.what {
+            TaskType::Wait { duration } => Some(duration),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn replace_restarts_or_keeps_the_step() {
+        let mut queue = TaskQueue::new("a", waits(&[1, 2, 3]));
+        queue.start(0);
+        queue.next(5);
+        queue.interrupt(ToioTask::new_get_away(DIST_AWAY), 6);
+        queue.next(7);
+        //the interruption doesn't count as a step
+        assert_eq!(queue.step(), 1);
+        assert_eq!(duration(&queue), Some(2));
+
+        queue.replace(waits(&[10, 20, 30]), ReloadMode::KeepStep, 8);
+        assert_eq!(queue.step(), 1);
+        assert_eq!(duration(&queue), Some(20));
+        assert_eq!(queue.current().unwrap().start_time, 8);
+
+        queue.replace(waits(&[10, 20, 30]), ReloadMode::Restart, 9);
+        assert_eq!(queue.step(), 0);
+        assert_eq!(duration(&queue), Some(10));
+
+        //a shorter list than where we were leaves nothing to do
+        queue.next(10);
+        queue.next(11);
+        queue.replace(waits(&[10]), ReloadMode::KeepStep, 12);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn timeouts() {
+        let mut task = ToioTask::new_get_close(DIST_CLOSE).timeout(1000);
+        task.start(500);
+        assert!(!task.timed_out(1500));
+        assert!(task.timed_out(1501));
+        assert!(!ToioTask::new_get_close(DIST_CLOSE).timed_out(u64::MAX));
+    }
+
+    #[test]
+    fn failure_policies() {
+        let failing = || ToioTask::new_get_close(DIST_CLOSE).timeout(100);
+
+        let mut queue = TaskQueue::new("q", vec![failing(), ToioTask::new_wait(1)]);
+        queue.start(0);
+        assert_eq!(queue.fail(200), FailureAction::Skipped);
+        assert_eq!(duration(&queue), Some(1));
+        assert_eq!(queue.step(), 1);
+
+        let retried = failing().on_failure(OnFailure::Retry(2));
+        let mut queue = TaskQueue::new("q", vec![retried, ToioTask::new_wait(1)]);
+        queue.start(0);
+        assert_eq!(queue.fail(200), FailureAction::Retried(1));
+        //started again
+        assert_eq!(queue.current().unwrap().start_time, 200);
+        assert_eq!(queue.fail(400), FailureAction::Retried(2));
+        assert_eq!(queue.fail(600), FailureAction::Skipped);
+        assert_eq!(duration(&queue), Some(1));
+
+        let aborting = failing().on_failure(OnFailure::AbortQueue);
+        let mut queue = TaskQueue::new("q", vec![aborting, ToioTask::new_wait(1)]);
+        queue.start(0);
+        assert_eq!(queue.fail(200), FailureAction::Aborted);
+        assert!(queue.is_empty());
+
+        let recovery = vec![ToioTask::new_wait(7), ToioTask::new_wait(8)];
+        let recovering = failing().on_failure(OnFailure::Recover(recovery));
+        let mut queue = TaskQueue::new("q", vec![recovering, ToioTask::new_wait(1)]);
+        queue.start(0);
+        assert_eq!(queue.fail(200), FailureAction::Recovering);
+        assert_eq!(duration(&queue), Some(7));
+        assert_eq!(queue.current().unwrap().start_time, 200);
+        queue.next(300);
+        queue.next(400);
+        assert_eq!(duration(&queue), Some(1));
+        //the recovery tasks are not steps of the list
+        assert_eq!(queue.step(), 1);
+    }
 }
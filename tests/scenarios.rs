@@ -0,0 +1,431 @@
+//! Whole task lists run against simulated cubes, the way the example app runs them.
+
+use nannou::prelude::*;
+use toio::choreography;
+use toio::cube::{CubeGroups, CubeId, CubeRegistry};
+use toio::engine::{BatteryAction, Engine, TaskEvent};
+use toio::protocol::InboundMessage;
+use toio::protocol::{OutboundCommand, TargetConfig};
+use toio::sim::{Simulator, POSITION_PERIOD};
+use toio::toiotask::{min_distance, Waypoint, DIST_CLOSE};
+use toio::toiotask::{FailureAction, Join, OnFailure, TaskQueue, ToioTask, DEFAULT_GROUP};
+
+//ms between two frames of the app
+const FRAME: u64 = 16;
+
+struct Stage {
+    sim: Simulator,
+    toio: CubeRegistry,
+    engine: Engine,
+    now: u64,
+    events: Vec<TaskEvent>,
+    //cubes off the mat, they only say they missed their position
+    lifted: Vec<i32>,
+}
+
+impl Stage {
+    //n simulated cubes, all in the default group
+    fn new(n: usize, queues: Vec<TaskQueue>) -> Self {
+        let mut groups = CubeGroups::new();
+        groups.insert(
+            DEFAULT_GROUP,
+            (0..n as i32).map(|i| CubeId::new(0, i)).collect(),
+        );
+        Stage {
+            sim: Simulator::new(0, n),
+            toio: CubeRegistry::new(),
+            engine: Engine::new(groups, queues),
+            now: 0,
+            events: Vec::new(),
+            lifted: Vec::new(),
+        }
+    }
+    fn frame(&mut self) {
+        if self.now % POSITION_PERIOD < FRAME {
+            for position in self.sim.positions() {
+                if let InboundMessage::Position {
+                    host,
+                    cube,
+                    x,
+                    y,
+                    angle,
+                    ..
+                } = position
+                {
+                    if self.lifted.contains(&cube) {
+                        self.toio.position_missed(CubeId::new(host, cube));
+                        continue;
+                    }
+                    self.toio
+                        .update(CubeId::new(host, cube), x, y, angle, self.now);
+                }
+            }
+        }
+        self.toio.check(self.now);
+        let tick = self.engine.update(&self.toio, self.now);
+        for (id, what) in tick.motors {
+            self.sim
+                .handle(OutboundCommand::motor(id.cube, what, 50), self.now);
+        }
+        for (_, command) in tick.targets {
+            self.sim.handle(command, self.now);
+        }
+        self.events.extend(tick.events);
+        self.sim.step(self.now, FRAME);
+        for result in self.sim.results() {
+            if let InboundMessage::TargetResult {
+                host,
+                cube,
+                request,
+                result,
+            } = result
+            {
+                self.toio
+                    .target_result(CubeId::new(host, cube), request, result, self.now);
+            }
+        }
+        self.now += FRAME;
+    }
+    //run until every queue is empty, false if it takes more than limit ms
+    fn run(&mut self, limit: u64) -> bool {
+        while self.now < limit {
+            if self.engine.is_empty() {
+                return true;
+            }
+            self.frame();
+        }
+        false
+    }
+    fn poses(&self) -> Vec<(f32, f32, f32)> {
+        let group = self.engine.groups.get(DEFAULT_GROUP).unwrap();
+        self.toio.poses(group).unwrap()
+    }
+}
+
+fn single_queue(tasks: Vec<ToioTask>) -> Vec<TaskQueue> {
+    vec![TaskQueue::new(DEFAULT_GROUP, tasks)]
+}
+
+#[test]
+fn hexagon() {
+    let mut stage = Stage::new(2, single_queue(choreography::hexagon()));
+    assert!(stage.run(180_000), "hexagon not done after 3 minutes");
+    //it ends with the pair close together
+    assert!(min_distance(&stage.poses()) < DIST_CLOSE);
+}
+
+#[test]
+fn pair_move() {
+    let tasks = vec![ToioTask::new_pair_move(-100.0, 50.0, 100.0, -50.0, 10.0)];
+    let mut stage = Stage::new(2, single_queue(tasks));
+    assert!(stage.run(30_000));
+    let poses = stage.poses();
+    assert!((vec2(poses[0].0, poses[0].1) - vec2(-100.0, 50.0)).magnitude() < 20.0);
+    assert!((vec2(poses[1].0, poses[1].1) - vec2(100.0, -50.0)).magnitude() < 20.0);
+}
+
+#[test]
+fn get_close_then_away() {
+    let tasks = vec![
+        ToioTask::new_get_close(DIST_CLOSE),
+        ToioTask::new_get_away(80.0),
+    ];
+    let mut stage = Stage::new(3, single_queue(tasks));
+    assert!(stage.run(30_000));
+    assert!(min_distance(&stage.poses()) > 70.0);
+}
+
+#[test]
+fn target_angles() {
+    //below ~0.3 rad aim_angle asks for speeds in the motor dead zone
+    let tasks = vec![ToioTask::new_target_angles(PI * 0.5, -PI * 0.5, 0.35)];
+    let mut stage = Stage::new(2, single_queue(tasks));
+    assert!(stage.run(10_000));
+}
+
+#[test]
+fn target_angle() {
+    let tasks = vec![ToioTask::new_target_angle(PI, 0.35)];
+    let mut stage = Stage::new(3, single_queue(tasks));
+    assert!(stage.run(10_000));
+}
+
+#[test]
+fn timed_tasks_take_their_time() {
+    let tasks = vec![
+        ToioTask::new_wait(1000),
+        ToioTask::new_spin(1000, 0.3),
+        ToioTask::new_wiggle(1000, 0.5),
+    ];
+    let mut stage = Stage::new(2, single_queue(tasks));
+    assert!(stage.run(10_000));
+    assert!(stage.now >= 3000);
+    assert!(stage.now < 3200);
+}
+
+#[test]
+fn queues_meet_at_syncs() {
+    let mut stage = Stage::new(2, vec![]);
+    stage.engine.groups.insert("a", vec![CubeId::new(0, 0)]);
+    stage.engine.groups.insert("b", vec![CubeId::new(0, 1)]);
+    stage.engine.queues = vec![
+        TaskQueue::new(
+            "a",
+            vec![
+                ToioTask::new_single_move(150.0, 0.0, 10.0).on("a"),
+                ToioTask::new_sync("there"),
+            ],
+        ),
+        TaskQueue::new(
+            "b",
+            vec![
+                ToioTask::new_sync("there"),
+                ToioTask::new_wait(10_000).on("b"),
+            ],
+        ),
+    ];
+    while stage.engine.queues[0]
+        .current()
+        .map(|t| t.sync_name().is_none())
+        == Some(true)
+    {
+        stage.frame();
+        //b waits for a
+        assert_eq!(stage.engine.queues[1].waiting_at(), Some("there"));
+    }
+    stage.frame();
+    assert!(stage.engine.queues[0].is_empty());
+    assert_eq!(stage.engine.queues[1].waiting_at(), None);
+}
+
+#[test]
+fn blocked_cube_times_out_and_recovers() {
+    //the mat ends before that, the cube gets stuck on the border
+    let recovery = vec![ToioTask::new_single_move(0.0, 0.0, 10.0)];
+    let tasks = vec![ToioTask::new_single_move(400.0, 0.0, 10.0)
+        .timeout(5000)
+        .on_failure(OnFailure::Recover(recovery))];
+    let mut stage = Stage::new(1, single_queue(tasks));
+    assert!(stage.run(20_000));
+    assert!(stage.events.iter().any(|e| matches!(
+        e,
+        TaskEvent::Failed {
+            action: FailureAction::Recovering,
+            ..
+        }
+    )));
+    let (x, y, _) = stage.poses()[0];
+    assert!(vec2(x, y).magnitude() < 10.0);
+}
+
+#[test]
+fn groups_move_in_parallel() {
+    let mut stage = Stage::new(2, vec![]);
+    stage.engine.groups.insert("a", vec![CubeId::new(0, 0)]);
+    stage.engine.groups.insert("b", vec![CubeId::new(0, 1)]);
+    let there_and_back = |group: &str, x: f32| {
+        ToioTask::new_repeat(
+            ToioTask::new_sequence(vec![
+                ToioTask::new_single_move(x, 100.0, 15.0).on(group),
+                ToioTask::new_single_move(x, -100.0, 15.0).on(group),
+            ]),
+            2,
+        )
+    };
+    let tasks = vec![ToioTask::new_parallel(
+        vec![there_and_back("a", -100.0), there_and_back("b", 100.0)],
+        Join::All,
+    )];
+    stage.engine.queues = single_queue(tasks);
+    assert!(stage.run(60_000));
+    let poses = stage.poses();
+    assert!((vec2(poses[0].0, poses[0].1) - vec2(-100.0, -100.0)).magnitude() < 20.0);
+    assert!((vec2(poses[1].0, poses[1].1) - vec2(100.0, -100.0)).magnitude() < 20.0);
+    //the parallel is one step of the queue
+    assert_eq!(
+        stage.events,
+        vec![TaskEvent::Done {
+            queue: DEFAULT_GROUP.to_string(),
+            step: 0
+        }]
+    );
+}
+
+#[test]
+fn button_cues_the_next_scene() {
+    let tasks = vec![
+        ToioTask::new_wait_button(None),
+        ToioTask::new_single_move(-100.0, 0.0, 15.0),
+    ];
+    let mut stage = Stage::new(1, single_queue(tasks));
+    stage.frame();
+    let start = stage.poses()[0];
+    while stage.now < 2000 {
+        stage.frame();
+    }
+    //nobody pressed the cube yet
+    assert_eq!(stage.poses()[0], start);
+    stage.engine.press(CubeId::new(0, 0), stage.now);
+    assert!(stage.run(20_000));
+    let (x, y, _) = stage.poses()[0];
+    assert!((vec2(x, y) - vec2(-100.0, 0.0)).magnitude() < 20.0);
+}
+
+#[test]
+fn button_ends_a_loop() {
+    let wiggle = ToioTask::new_wiggle(300, 0.5);
+    let tasks = vec![ToioTask::new_loop_until(
+        wiggle,
+        ToioTask::new_wait_button(Some(1)),
+    )];
+    let mut stage = Stage::new(2, single_queue(tasks));
+    //still going after a few rounds
+    assert!(!stage.run(3000));
+    //not the cube it waits for
+    stage.engine.press(CubeId::new(0, 0), stage.now);
+    assert!(!stage.run(3500));
+    stage.engine.press(CubeId::new(0, 1), stage.now);
+    assert!(stage.run(4000));
+}
+
+#[test]
+fn paused_routine_holds_still() {
+    let tasks = vec![ToioTask::new_single_move(-100.0, 0.0, 15.0)];
+    let mut stage = Stage::new(1, single_queue(tasks));
+    while stage.now < 500 {
+        stage.frame();
+    }
+    stage.engine.pause(stage.now);
+    //the last motor command runs out first
+    for _ in 0..10 {
+        stage.frame();
+    }
+    let held = stage.poses()[0];
+    while stage.now < 3000 {
+        stage.frame();
+    }
+    assert_eq!(stage.poses()[0], held);
+    stage.engine.resume(stage.now);
+    assert!(stage.run(20_000));
+}
+
+#[test]
+fn lifted_cube_holds_its_queue_only() {
+    let mut stage = Stage::new(2, vec![]);
+    stage.engine.groups.insert("a", vec![CubeId::new(0, 0)]);
+    stage.engine.groups.insert("b", vec![CubeId::new(0, 1)]);
+    stage.engine.queues = vec![
+        TaskQueue::new("a", vec![ToioTask::new_spin(3000, 0.3).on("a")]),
+        TaskQueue::new("b", vec![ToioTask::new_spin(3000, 0.3).on("b")]),
+    ];
+    while stage.now < 1000 {
+        stage.frame();
+    }
+    stage.lifted.push(1);
+    while stage.now < 3200 {
+        stage.frame();
+    }
+    assert!(stage.engine.queues[0].is_empty());
+    assert!(stage.engine.queues[1].is_held());
+    stage.lifted.clear();
+    assert!(stage.run(10_000));
+    let events: Vec<_> = stage
+        .events
+        .iter()
+        .filter(|e| !matches!(e, TaskEvent::Done { .. }))
+        .collect();
+    assert_eq!(
+        events,
+        vec![
+            &TaskEvent::Held {
+                queue: "b".to_string(),
+                cube: CubeId::new(0, 1)
+            },
+            &TaskEvent::Released {
+                queue: "b".to_string()
+            }
+        ]
+    );
+    //b still had about 2 s of spin to do
+    assert!(stage.now > 4500, "{}", stage.now);
+}
+
+#[test]
+fn low_cube_parks_and_a_spare_takes_over() {
+    let tasks = vec![ToioTask::new_pair_move(-100.0, 0.0, 100.0, 0.0, 15.0)];
+    let mut stage = Stage::new(3, single_queue(tasks));
+    let (a, b, spare) = (CubeId::new(0, 0), CubeId::new(0, 1), CubeId::new(0, 2));
+    stage.engine.groups.insert(DEFAULT_GROUP, vec![a, b]);
+    stage.engine.spares.push(spare);
+    stage.engine.battery.action = BatteryAction::Park(vec2(0.0, -150.0));
+    stage.frame();
+    stage.toio.battery(b, 10);
+    assert!(stage.run(30_000));
+    assert_eq!(
+        stage.engine.groups.get(DEFAULT_GROUP),
+        Some(&[a, spare][..])
+    );
+    let poses = stage.toio.poses(&[a, b, spare]).unwrap();
+    assert!((vec2(poses[0].0, poses[0].1) - vec2(-100.0, 0.0)).magnitude() < 20.0);
+    assert!((vec2(poses[1].0, poses[1].1) - vec2(0.0, -150.0)).magnitude() < 20.0);
+    assert!((vec2(poses[2].0, poses[2].1) - vec2(100.0, 0.0)).magnitude() < 20.0);
+}
+
+#[test]
+fn pair_drives_itself_through_its_paths() {
+    let paths = vec![
+        vec![
+            Waypoint::new(-100.0, 0.0, None),
+            Waypoint::new(-100.0, 100.0, Some(PI * 0.5)),
+        ],
+        vec![
+            Waypoint::new(100.0, 0.0, None),
+            Waypoint::new(100.0, 100.0, Some(PI * 0.5)),
+        ],
+    ];
+    let tasks = vec![ToioTask::new_follow_path(paths, TargetConfig::default())];
+    let mut stage = Stage::new(2, single_queue(tasks));
+    assert!(stage.run(30_000));
+    assert!(stage
+        .events
+        .iter()
+        .any(|event| matches!(event, TaskEvent::Done { .. })));
+    let poses = stage
+        .toio
+        .poses(&[CubeId::new(0, 0), CubeId::new(0, 1)])
+        .unwrap();
+    assert!((vec2(poses[0].0, poses[0].1) - vec2(-100.0, 100.0)).magnitude() < 20.0);
+    assert!((vec2(poses[1].0, poses[1].1) - vec2(100.0, 100.0)).magnitude() < 20.0);
+}
+
+#[test]
+fn pause_while_held_stays_after_release() {
+    let tasks = vec![ToioTask::new_spin(3000, 0.3)];
+    let mut stage = Stage::new(1, single_queue(tasks));
+    while stage.now < 1000 {
+        stage.frame();
+    }
+    stage.lifted.push(0);
+    while stage.now < 2000 {
+        stage.frame();
+    }
+    assert!(stage.engine.queues[0].is_held());
+    //the operator pauses while the cube is away, resuming doesn't drive it blind
+    stage.engine.pause(stage.now);
+    stage.engine.resume(stage.now);
+    assert!(stage.engine.queues[0].is_held());
+    stage.engine.pause(stage.now);
+    stage.lifted.clear();
+    while stage.now < 3000 {
+        stage.frame();
+    }
+    assert!(!stage.engine.queues[0].is_held());
+    assert!(stage.engine.queues[0].is_paused());
+    let still = stage.poses()[0];
+    while stage.now < 4000 {
+        stage.frame();
+    }
+    assert_eq!(stage.poses()[0], still);
+    stage.engine.resume(stage.now);
+    assert!(stage.run(10_000));
+}